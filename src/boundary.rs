@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::WorldBounds;
+
+// What happens to cells at the edge of the world
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    #[default]
+    Clamp,  // Moved back inside by their radius, velocity untouched
+    Reflect { restitution: f32 },  // Bounce off the walls, keeping `restitution` of the normal velocity
+    Toroidal,  // Leave one side to come back in on the other, forces act across the edges
+    Open  // No walls
+}
+
+// Keeps a cell of the given radius within the bounds according to `mode`
+pub fn apply_boundary(mode: BoundaryMode, bounds: &WorldBounds, pos: &mut Vec2, vel: &mut Vec2, radius: f32) {
+    let half = Vec2::new(bounds.width, bounds.height) * 0.5;
+    for axis in 0..2 {
+        let h = half[axis];
+        match mode {
+            BoundaryMode::Clamp => {
+                if pos[axis] > h {
+                    pos[axis] = h - radius;
+                }
+                else if pos[axis] < -h {
+                    pos[axis] = -h + radius;
+                }
+            }
+            BoundaryMode::Reflect { restitution } => {
+                // Cells wider than the world settle in the middle
+                let limit = (h - radius).max(0.);
+                if pos[axis] > limit {
+                    pos[axis] = limit;
+                    vel[axis] = -vel[axis].abs() * restitution;
+                }
+                else if pos[axis] < -limit {
+                    pos[axis] = -limit;
+                    vel[axis] = vel[axis].abs() * restitution;
+                }
+            }
+            BoundaryMode::Toroidal => {
+                pos[axis] = (pos[axis] + h).rem_euclid(2. * h) - h;
+            }
+            BoundaryMode::Open => ()
+        }
+    }
+}
+
+// Shortest offset from one point to another, across the edges in a toroidal world
+pub fn minimum_image(delta: Vec2, mode: BoundaryMode, bounds: &WorldBounds) -> Vec2 {
+    match mode {
+        BoundaryMode::Toroidal => {
+            let size = Vec2::new(bounds.width, bounds.height);
+            delta - size * (delta / size).round()
+        }
+        _ => delta
+    }
+}
+
+// Offsets at which to look for neighbours of a point so that every cell within `range`
+// of it, measured by minimum image, is found
+pub fn image_offsets(p: Vec2, range: f32, mode: BoundaryMode, bounds: &WorldBounds) -> Vec<Vec2> {
+    if mode != BoundaryMode::Toroidal {
+        return vec![Vec2::ZERO]
+    }
+    let axis_offsets = |x: f32, size: f32| {
+        let mut offsets = vec![0.];
+        // Near the right edge, the cells by the left edge are found one world width to the left
+        if x + range > size * 0.5 { offsets.push(-size) }
+        if x - range < -size * 0.5 { offsets.push(size) }
+        offsets
+    };
+    let xs = axis_offsets(p.x, bounds.width);
+    let ys = axis_offsets(p.y, bounds.height);
+    ys.iter().flat_map(|y| xs.iter().map(move |x| Vec2::new(*x, *y))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> WorldBounds {
+        WorldBounds { width: 200., height: 100. }
+    }
+
+    #[test]
+    fn clamp_boundary_moves_cells_inside() {
+        let mut pos = Vec2::new(120., -60.);
+        let mut vel = Vec2::new(3., -2.);
+        apply_boundary(BoundaryMode::Clamp, &bounds(), &mut pos, &mut vel, 5.);
+        assert_eq!(pos, Vec2::new(95., -45.));
+        assert_eq!(vel, Vec2::new(3., -2.));
+        let mut inside = Vec2::new(99., 0.);
+        apply_boundary(BoundaryMode::Clamp, &bounds(), &mut inside, &mut vel, 5.);
+        assert_eq!(inside, Vec2::new(99., 0.));
+    }
+
+    #[test]
+    fn reflect_boundary_bounces_with_restitution() {
+        let mode = BoundaryMode::Reflect { restitution: 0.5 };
+        let mut pos = Vec2::new(98., -49.);
+        let mut vel = Vec2::new(4., -2.);
+        apply_boundary(mode, &bounds(), &mut pos, &mut vel, 5.);
+        assert_eq!(pos, Vec2::new(95., -45.));
+        assert_eq!(vel, Vec2::new(-2., 1.));
+        // Already heading back inside, only the position is corrected
+        let mut pos = Vec2::new(-97., 0.);
+        let mut vel = Vec2::new(1., 0.);
+        apply_boundary(mode, &bounds(), &mut pos, &mut vel, 5.);
+        assert_eq!((pos, vel), (Vec2::new(-95., 0.), Vec2::new(0.5, 0.)));
+        // Too big to fit
+        let mut pos = Vec2::new(10., 10.);
+        apply_boundary(mode, &bounds(), &mut pos, &mut vel, 60.);
+        assert_eq!(pos.y, 0.);
+    }
+
+    #[test]
+    fn toroidal_boundary_wraps_and_uses_minimum_images() {
+        let mut pos = Vec2::new(105., -230.);
+        let mut vel = Vec2::new(3., -2.);
+        apply_boundary(BoundaryMode::Toroidal, &bounds(), &mut pos, &mut vel, 5.);
+        assert!((pos - Vec2::new(-95., -30.)).length() < 1e-4, "{:?}", pos);
+        assert_eq!(vel, Vec2::new(3., -2.));
+        let delta = minimum_image(Vec2::new(-90., 0.) - Vec2::new(90., 40.), BoundaryMode::Toroidal, &bounds());
+        assert!((delta - Vec2::new(20., -40.)).length() < 1e-4, "{:?}", delta);
+        let delta = minimum_image(Vec2::new(0., 45.) - Vec2::new(0., -45.), BoundaryMode::Toroidal, &bounds());
+        assert!((delta - Vec2::new(0., -10.)).length() < 1e-4, "{:?}", delta);
+        assert_eq!(image_offsets(Vec2::ZERO, 10., BoundaryMode::Toroidal, &bounds()), [Vec2::ZERO]);
+        assert_eq!(image_offsets(Vec2::new(95., 45.), 10., BoundaryMode::Toroidal, &bounds()).len(), 4);
+    }
+
+    #[test]
+    fn open_boundary_leaves_cells_alone() {
+        let mut pos = Vec2::new(1e4, -1e4);
+        let mut vel = Vec2::new(3., -2.);
+        apply_boundary(BoundaryMode::Open, &bounds(), &mut pos, &mut vel, 5.);
+        assert_eq!((pos, vel), (Vec2::new(1e4, -1e4), Vec2::new(3., -2.)));
+        let delta = Vec2::new(1e3, 0.);
+        assert_eq!(minimum_image(delta, BoundaryMode::Open, &bounds()), delta);
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use bevy_genetic_particles::EventLevel;
+
+const USAGE: &str = "Usage: bevy-genetic-particles [--config <path>] [--seed <n>] [--snapshot <path>] [--save-snapshot <path>] [--genomes <path>] [--lineage <path>] [--species-log <path>] [--stats <path>] [--event-log <path> [--event-level info|debug]] [--headless [--ticks <n>] [--seconds <s>]]";
+
+// Command line options
+#[derive(Clone, Default, Debug)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub snapshot: Option<PathBuf>,  // Start from this snapshot instead of a random population
+    pub save_snapshot: Option<PathBuf>,  // Write a snapshot here on exit
+    pub genomes: Option<PathBuf>,  // Encoded genomes to build the initial population from
+    pub lineage: Option<PathBuf>,  // Write the lineage here on exit
+    pub species_log: Option<PathBuf>,  // Write species member counts over time here on exit
+    pub stats: Option<PathBuf>,  // Record population statistics here while running
+    pub event_log: Option<PathBuf>,  // Write cell events here as JSON lines
+    pub event_level: EventLevel,  // Which events the event log keeps
+    pub headless: bool,
+    pub ticks: Option<u64>,  // Headless tick budget
+    pub seconds: Option<f64>  // Headless wall time budget
+}
+
+impl Args {
+
+    // Parses the process arguments, printing usage and exiting on error
+    pub fn from_env() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(args) => args,
+            Err(msg) if msg.is_empty() => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(msg) => {
+                eprintln!("{}\n{}", msg, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut it: I) -> Result<Self, String> {
+        let mut args = Self::default();
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--config" => args.config = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--seed" => args.seed = Some(parse(&arg, &mut it)?),
+                "--snapshot" => args.snapshot = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--save-snapshot" => args.save_snapshot = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--genomes" => args.genomes = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--lineage" => args.lineage = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--species-log" => args.species_log = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--stats" => args.stats = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--event-log" => args.event_log = Some(PathBuf::from(value(&arg, &mut it)?)),
+                "--event-level" => args.event_level = value(&arg, &mut it)?.parse()?,
+                "--headless" => args.headless = true,
+                "--ticks" => args.ticks = Some(parse(&arg, &mut it)?),
+                "--seconds" => args.seconds = Some(parse(&arg, &mut it)?),
+                "-h" | "--help" => return Err(String::new()),  // Empty message prints usage only
+                _ => return Err(format!("Unknown argument {}", arg))
+            }
+        }
+        if args.snapshot.is_some() && args.genomes.is_some() {
+            return Err("--snapshot and --genomes can't be combined".to_string())
+        }
+        if !args.headless && (args.ticks.is_some() || args.seconds.is_some()) {
+            return Err("--ticks and --seconds require --headless".to_string())
+        }
+        if args.event_log.is_none() && args.event_level != EventLevel::default() {
+            return Err("--event-level requires --event-log".to_string())
+        }
+        if args.seconds.is_some_and(|s| !(s.is_finite() && s >= 0.)) {
+            return Err("--seconds must be a non-negative number".to_string())
+        }
+        Ok(args)
+    }
+
+}
+
+fn value<I: Iterator<Item = String>>(flag: &str, it: &mut I) -> Result<String, String> {
+    it.next().ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn parse<T: std::str::FromStr, I: Iterator<Item = String>>(flag: &str, it: &mut I) -> Result<T, String> {
+    let v = value(flag, it)?;
+    v.parse().map_err(|_| format!("Invalid value {} for {}", v, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Args, String> {
+        Args::parse(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn headless_budgets_parse() {
+        let a = args("--headless --ticks 100 --seconds 2.5 --seed 7").unwrap();
+        assert!(a.headless);
+        assert_eq!((a.ticks, a.seconds, a.seed), (Some(100), Some(2.5), Some(7)));
+    }
+
+    #[test]
+    fn conflicting_arguments_are_rejected() {
+        assert_eq!(args("--ticks 100").err().unwrap(), "--ticks and --seconds require --headless");
+        assert_eq!(args("--seconds 1").err().unwrap(), "--ticks and --seconds require --headless");
+        assert_eq!(args("--headless --seconds -1").err().unwrap(), "--seconds must be a non-negative number");
+        assert_eq!(args("--snapshot a.json --genomes b.txt").err().unwrap(), "--snapshot and --genomes can't be combined");
+        assert_eq!(args("--event-level debug").err().unwrap(), "--event-level requires --event-log");
+        assert_eq!(args("--ticks").err().unwrap(), "--ticks expects a value");
+        assert_eq!(args("--bogus").err().unwrap(), "Unknown argument --bogus");
+        assert_eq!(args("--help").err().unwrap(), "");
+    }
+}
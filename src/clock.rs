@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::SimulationTick;
+
+// Ticks per second the unitless velocities and growth rates were tuned for
+pub const REFERENCE_TICKS_PER_SECOND: f64 = 60.;
+
+// Division is checked this many times per simulated second
+pub const DIVISION_CHECKS_PER_SECOND: f64 = 10.;
+
+// Simulated time beyond this many ticks per update is dropped, so a slow frame can't
+// snowball into ever longer ones
+const MAX_TICKS_PER_UPDATE: u32 = 32;
+
+pub const TIME_SCALE_HOTKEYS: [(KeyCode, f64); 4] = [
+    (KeyCode::Key1, 0.5),
+    (KeyCode::Key2, 1.),
+    (KeyCode::Key3, 2.),
+    (KeyCode::Key4, 10.)
+];
+
+pub const PAUSE_HOTKEY: KeyCode = KeyCode::Space;
+
+#[derive(RunCriteriaLabel)]
+pub struct PhysicsStep;
+
+// The parts of a physics tick, in the order they run. Rendering follows in PostUpdate.
+#[derive(SystemLabel)]
+pub enum PhysicsSystem {
+    Spawn,
+    Forces,  // Accelerations and contacts from the positions the tick starts with
+    Predation,
+    Integrate,
+    Metabolism,  // Applies each cell's Growth, which nothing may touch in between
+    Forage,
+    Divide,
+    Death
+}
+
+// Turns frame time into a whole number of fixed length simulation ticks
+#[derive(Resource)]
+pub struct SimulationClock {
+    pub step: Duration,
+    pub time_scale: f64,
+    pub paused: bool,
+    division_every: u64,  // In ticks
+    accumulator: Duration,
+    looping: bool
+}
+
+impl SimulationClock {
+
+    pub fn new(ticks_per_second: f64) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1. / ticks_per_second),
+            time_scale: 1.,
+            paused: false,
+            division_every: ((ticks_per_second / DIVISION_CHECKS_PER_SECOND).round() as u64).max(1),
+            accumulator: Duration::ZERO,
+            looping: false
+        }
+    }
+
+    // Length of a tick in reference ticks, what per tick rates are multiplied by
+    pub fn dt(&self) -> f32 {
+        (self.step.as_secs_f64() * REFERENCE_TICKS_PER_SECOND) as f32
+    }
+
+    // Whole ticks closest to a span of simulated seconds, at least one
+    pub fn ticks_in(&self, seconds: f64) -> u64 {
+        ((seconds / self.step.as_secs_f64()).round() as u64).max(1)
+    }
+
+    // Simulated seconds at the end of a tick
+    pub fn seconds_at(&self, tick: u64) -> f64 {
+        tick as f64 * self.step.as_secs_f64()
+    }
+
+}
+
+// Runs the physics once per tick of accumulated, scaled frame time, advancing SimulationTick
+pub fn physics_step(
+    time: Res<Time>,
+    mut clock: ResMut<SimulationClock>,
+    mut tick: ResMut<SimulationTick>
+) -> ShouldRun {
+    if !clock.looping {
+        if !clock.paused {
+            let elapsed = time.delta().mul_f64(clock.time_scale);
+            clock.accumulator += elapsed;
+        }
+        clock.accumulator = clock.accumulator.min(clock.step * MAX_TICKS_PER_UPDATE);
+    }
+    if clock.accumulator >= clock.step {
+        let step = clock.step;
+        clock.accumulator -= step;
+        clock.looping = true;
+        tick.0 += 1;
+        ShouldRun::YesAndCheckAgain
+    }
+    else {
+        clock.looping = false;
+        ShouldRun::No
+    }
+}
+
+// Narrows PhysicsStep down to the ticks `n` is a multiple of `every` on
+pub fn every_ticks(physics: ShouldRun, n: u64, every: u64) -> ShouldRun {
+    match physics {
+        ShouldRun::No => ShouldRun::No,
+        ShouldRun::Yes | ShouldRun::YesAndCheckAgain if n.is_multiple_of(every) => ShouldRun::YesAndCheckAgain,
+        _ => ShouldRun::NoAndCheckAgain
+    }
+}
+
+// Piped from PhysicsStep, runs on the ticks division is due
+pub fn division_step(
+    In(physics): In<ShouldRun>,
+    clock: Res<SimulationClock>,
+    tick: Res<SimulationTick>
+) -> ShouldRun {
+    every_ticks(physics, tick.0, clock.division_every)
+}
+
+pub fn time_scale_hotkey_system(
+    keys: Res<Input<KeyCode>>,
+    mut clock: ResMut<SimulationClock>
+) {
+    if keys.just_pressed(PAUSE_HOTKEY) {
+        clock.paused = !clock.paused;
+        info!("{}", if clock.paused { "Paused" } else { "Resumed" });
+    }
+    for (key, scale) in TIME_SCALE_HOTKEYS {
+        if keys.just_pressed(key) {
+            clock.time_scale = scale;
+            info!("Time scale {}x", scale);
+        }
+    }
+}
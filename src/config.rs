@@ -0,0 +1,313 @@
+use std::{fmt, fs, path::Path};
+
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::{range_to_u8, u8_to_range, BoundaryMode, CrossoverConfig, DragConfig, Integrator, MetabolismConfig, MutationConfig, MutationOperator, NutrientConfig, SpeciesConfig, StatsConfig, CHARGE_COUNT, REFERENCE_TICKS_PER_SECOND};
+
+// Used when no --config is passed. A missing file at this path is not an error.
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+// The interval a u8 gene is decoded into
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GeneRange {
+    pub min: f32,
+    pub max: f32
+}
+
+impl GeneRange {
+
+    pub const fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    #[inline(always)]
+    pub fn decode(&self, i: u8) -> f32 {
+        u8_to_range(i, self.min, self.max)
+    }
+
+    #[inline(always)]
+    pub fn encode(&self, v: f32) -> u8 {
+        range_to_u8(self.min, self.max, v)
+    }
+
+    pub fn midpoint(&self) -> f32 {
+        0.5 * (self.min + self.max)
+    }
+
+}
+
+// Tunable parameters of the simulation. Every field may be omitted from the config file.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub evolution_probability: f32,  // Likelihood that any given gene will increment
+    pub mutation: MutationConfig,
+    pub crossover: CrossoverConfig,
+    pub species: SpeciesConfig,
+    pub stats: StatsConfig,
+    pub minimum_size: f32,
+    pub world_width: f32,  // Size of the world when headless, windowed runs use the window size
+    pub world_height: f32,
+    pub boundary: BoundaryMode,
+    pub ticks_per_second: f64,  // Physics steps per simulated second
+    pub integrator: Integrator,
+    pub drag: DragConfig,
+    pub metabolism: MetabolismConfig,
+    pub nutrients: NutrientConfig,
+    pub charge_types: usize,  // Distinct charges cells tell apart, each genome holds an interaction table entry per type
+    pub division_prob: GeneRange,
+    pub division_asym: GeneRange,
+    pub division_min_size: GeneRange,
+    pub repulsion_range: GeneRange,
+    pub repulsion_strength: GeneRange,
+    pub force_range: GeneRange,
+    pub force_strength: GeneRange,
+    pub eat_rate: GeneRange,
+    pub uptake: GeneRange  // Nutrients drawn per reference tick
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            evolution_probability: 0.2,
+            mutation: MutationConfig::default(),
+            crossover: CrossoverConfig::default(),
+            species: SpeciesConfig::default(),
+            stats: StatsConfig::default(),
+            minimum_size: 40.,
+            world_width: 1000.,
+            world_height: 1000.,
+            boundary: BoundaryMode::default(),
+            ticks_per_second: 60.,
+            integrator: Integrator::default(),
+            drag: DragConfig::default(),
+            metabolism: MetabolismConfig::default(),
+            nutrients: NutrientConfig::default(),
+            charge_types: CHARGE_COUNT,
+            division_prob: GeneRange::new(0.7, 0.8),
+            division_asym: GeneRange::new(0.4, 0.5),
+            division_min_size: GeneRange::new(300., 320.),
+            repulsion_range: GeneRange::new(8.0, 10.0),
+            repulsion_strength: GeneRange::new(8.0, 78.0),
+            force_range: GeneRange::new(50.0, 1000.0),
+            force_strength: GeneRange::new(-0.2, 0.2),
+            eat_rate: GeneRange::new(-2.0, 2.0),
+            uptake: GeneRange::new(0.0, 0.2)
+        }
+    }
+}
+
+impl SimulationConfig {
+
+    // Loads `path` if given, otherwise DEFAULT_CONFIG_PATH if it exists, otherwise the defaults
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::load_or(path, Path::new(DEFAULT_CONFIG_PATH))
+    }
+
+    fn load_or(path: Option<&Path>, default_path: &Path) -> Result<Self, ConfigError> {
+        match path {
+            Some(path) => Self::load(path),
+            None => {
+                if default_path.exists() {
+                    Self::load(default_path)
+                }
+                else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+
+    // Parses a .ron file as RON and anything else as JSON
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.display().to_string(), e))?;
+        let config: Self = if path.extension().is_some_and(|ext| ext == "ron") {
+            ron::from_str(&text).map_err(|e| ConfigError::Parse(path.display().to_string(), e.to_string()))?
+        }
+        else {
+            serde_json::from_str(&text).map_err(|e| ConfigError::Parse(path.display().to_string(), e.to_string()))?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, range) in self.gene_ranges() {
+            if !range.min.is_finite() || !range.max.is_finite() || range.min > range.max {
+                return Err(ConfigError::Invalid(format!("{} must satisfy min <= max, got min {} max {}", name, range.min, range.max)))
+            }
+        }
+        // A probability, and the share of the parent's mass the first daughter gets
+        for (name, range) in [("division_prob", &self.division_prob), ("division_asym", &self.division_asym)] {
+            if range.min < 0. || range.max > 1. {
+                return Err(ConfigError::Invalid(format!("{} must lie within 0..=1, got min {} max {}", name, range.min, range.max)))
+            }
+        }
+        if !(0.0..=1.0).contains(&self.evolution_probability) {
+            return Err(ConfigError::Invalid(format!("evolution_probability must be within 0..=1, got {}", self.evolution_probability)))
+        }
+        for operator in self.mutation.operators() {
+            if let MutationOperator::Gaussian { sigma } = operator {
+                if !sigma.is_finite() || *sigma <= 0. {
+                    return Err(ConfigError::Invalid(format!("gaussian mutation sigma must be positive, got {}", sigma)))
+                }
+            }
+        }
+        if let BoundaryMode::Reflect { restitution } = self.boundary {
+            if !(restitution >= 0. && restitution.is_finite()) {
+                return Err(ConfigError::Invalid(format!("reflect restitution must be a non-negative number, got {}", restitution)))
+            }
+        }
+        if !(self.species.threshold >= 0. && self.species.threshold.is_finite()) {
+            return Err(ConfigError::Invalid(format!("species threshold must be a non-negative number, got {}", self.species.threshold)))
+        }
+        if !(self.species.interval > 0. && self.species.interval.is_finite()) {
+            return Err(ConfigError::Invalid(format!("species interval must be positive, got {}", self.species.interval)))
+        }
+        if !(self.ticks_per_second > 0. && self.ticks_per_second.is_finite()) {
+            return Err(ConfigError::Invalid(format!("ticks_per_second must be positive, got {}", self.ticks_per_second)))
+        }
+        let non_negative = [
+            ("drag linear", self.drag.linear),
+            ("drag quadratic", self.drag.quadratic),
+            ("metabolism uptake", self.metabolism.uptake),
+            ("metabolism maintenance", self.metabolism.maintenance),
+            ("metabolism movement_cost", self.metabolism.movement_cost),
+            ("nutrients capacity", self.nutrients.capacity),
+            ("nutrients regeneration", self.nutrients.regeneration),
+            ("nutrients diffusion", self.nutrients.diffusion)
+        ];
+        for (name, v) in non_negative {
+            if !(v >= 0. && v.is_finite()) {
+                return Err(ConfigError::Invalid(format!("{} must be a non-negative number, got {}", name, v)))
+            }
+        }
+        if let Some(max_speed) = self.drag.max_speed {
+            if max_speed.is_nan() || max_speed <= 0. {
+                return Err(ConfigError::Invalid(format!("drag max_speed must be positive, got {}", max_speed)))
+            }
+        }
+        if !(0.0..=1.0).contains(&self.metabolism.predation_efficiency) {
+            return Err(ConfigError::Invalid(format!("metabolism predation_efficiency must be within 0..=1, got {}", self.metabolism.predation_efficiency)))
+        }
+        if !(self.nutrients.cell_size > 0. && self.nutrients.cell_size.is_finite()) {
+            return Err(ConfigError::Invalid(format!("nutrients cell_size must be positive, got {}", self.nutrients.cell_size)))
+        }
+        // Explicit diffusion overshoots beyond a quarter per tick
+        let diffusion_per_tick = self.nutrients.diffusion as f64 * REFERENCE_TICKS_PER_SECOND / self.ticks_per_second;
+        if diffusion_per_tick > 0.25 {
+            return Err(ConfigError::Invalid(format!("nutrients diffusion of {} is unstable at {} ticks per second", self.nutrients.diffusion, self.ticks_per_second)))
+        }
+        if !(self.stats.interval > 0. && self.stats.interval.is_finite()) {
+            return Err(ConfigError::Invalid(format!("stats interval must be positive, got {}", self.stats.interval)))
+        }
+        if !(1..=CHARGE_COUNT).contains(&self.charge_types) {
+            return Err(ConfigError::Invalid(format!("charge_types must be within 1..={}, got {}", CHARGE_COUNT, self.charge_types)))
+        }
+        for (name, v) in [("minimum_size", self.minimum_size), ("world_width", self.world_width), ("world_height", self.world_height)] {
+            if v.is_nan() || v <= 0. {
+                return Err(ConfigError::Invalid(format!("{} must be positive, got {}", name, v)))
+            }
+        }
+        Ok(())
+    }
+
+    fn gene_ranges(&self) -> [(&'static str, &GeneRange); 9] {
+        [
+            ("division_prob", &self.division_prob),
+            ("division_asym", &self.division_asym),
+            ("division_min_size", &self.division_min_size),
+            ("repulsion_range", &self.repulsion_range),
+            ("repulsion_strength", &self.repulsion_strength),
+            ("force_range", &self.force_range),
+            ("force_strength", &self.force_strength),
+            ("eat_rate", &self.eat_rate),
+            ("uptake", &self.uptake)
+        ]
+    }
+
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, String),
+    Invalid(String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Failed to read config {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "Failed to parse config {}: {}", path, e),
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn division_ranges_must_be_fractions() {
+        assert!(SimulationConfig::default().validate().is_ok());
+        let config = SimulationConfig { division_asym: GeneRange::new(0.5, 1.5), ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = SimulationConfig { division_prob: GeneRange::new(-0.1, 0.5), ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn every_gene_value_round_trips() {
+        for range in [GeneRange::new(0., 1.), GeneRange::new(-2., 2.), GeneRange::new(50., 1000.)] {
+            for i in 0..=u8::MAX {
+                let v = range.decode(i);
+                assert!(range.min <= v && v <= range.max);
+                assert_eq!(range.encode(v), i);
+            }
+            assert_eq!(range.encode(range.min - 1.), 0);
+            assert_eq!(range.encode(range.max + 1.), u8::MAX);
+        }
+    }
+
+    // Writes `text` to a temporary file with the given extension and loads it
+    fn load_text(name: &str, text: &str) -> Result<SimulationConfig, ConfigError> {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let config = SimulationConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        let config = load_text("config.ron", "(minimum_size: 12.0, boundary: toroidal)").unwrap();
+        assert_eq!((config.minimum_size, config.boundary), (12., BoundaryMode::Toroidal));
+        assert_eq!(config.world_width, SimulationConfig::default().world_width);
+        let config = load_text("config.json", r#"{ "minimum_size": 12.0 }"#).unwrap();
+        assert_eq!(config.minimum_size, 12.);
+        // JSON is not RON and the other way round
+        assert!(matches!(load_text("config.ron", r#"{ "minimum_size": 12.0 }"#), Err(ConfigError::Parse(..))));
+        assert!(matches!(load_text("config.txt", "(minimum_size: 12.0)"), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let e = load_text("config.json", r#"{ "minimum_sise": 12.0 }"#).err().unwrap();
+        assert!(matches!(e, ConfigError::Parse(..)));
+        assert!(e.to_string().contains("minimum_sise"), "{}", e);
+        assert!(load_text("config.json", r#"{ "nutrients": { "enabeld": false } }"#).is_err());
+    }
+
+    #[test]
+    fn missing_default_file_falls_back_to_defaults() {
+        let missing = std::env::temp_dir().join(format!("{}-missing.json", std::process::id()));
+        assert_eq!(SimulationConfig::load_or(None, &missing).unwrap(), SimulationConfig::default());
+        // An explicitly passed file has to exist
+        assert!(matches!(SimulationConfig::load_or(Some(&missing), &missing), Err(ConfigError::Io(..))));
+    }
+}
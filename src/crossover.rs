@@ -0,0 +1,120 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{Gene, Genome};
+
+// How daughters combine the genes of a dividing cell and its partner
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossoverMode {
+    Off,  // Asexual division
+    OnePoint,  // Genes after a random cut come from the partner
+    TwoPoint,  // Genes between two random cuts come from the partner
+    Uniform  // Each gene value comes from either parent with equal odds
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrossoverConfig {
+    pub mode: CrossoverMode,
+    pub compatibility_tolerance: u8  // Largest difference in compatibility genes that still allows mating
+}
+
+impl Default for CrossoverConfig {
+    fn default() -> Self {
+        Self {
+            mode: CrossoverMode::Off,
+            compatibility_tolerance: 16
+        }
+    }
+}
+
+// Cells each cell overlapped in the last force pass, in query order
+#[derive(Resource, Default)]
+pub struct Contacts(pub HashMap<Entity, Vec<Entity>>);
+
+impl Genome {
+
+    pub fn compatible(&self, other: &Genome, config: &CrossoverConfig) -> bool {
+        self.compatibility.abs_diff(other.compatibility) <= config.compatibility_tolerance
+            && self.charge_types() == other.charge_types()
+    }
+
+    // Genes of `a` with a part taken from `b`, treating all gene values as one sequence
+    pub fn crossover(a: &Genome, b: &Genome, mode: CrossoverMode, rng: &mut impl Rng) -> Genome {
+        let len: usize = Gene::ALL.iter().map(|gene| a.gene(*gene).len()).sum();
+        let (lo, hi) = match mode {
+            CrossoverMode::Off | CrossoverMode::Uniform => (len, len),
+            CrossoverMode::OnePoint => (rng.gen_range(0..=len), len),
+            CrossoverMode::TwoPoint => {
+                let (x, y) = (rng.gen_range(0..=len), rng.gen_range(0..=len));
+                (x.min(y), x.max(y))
+            }
+        };
+        let mut child = a.clone();
+        let mut i = 0;
+        for gene in Gene::ALL {
+            for (v, w) in child.gene_mut(gene).iter_mut().zip(b.gene(gene)) {
+                let from_b = match mode {
+                    CrossoverMode::Uniform => rng.gen(),
+                    _ => lo <= i && i < hi
+                };
+                if from_b {
+                    *v = *w;
+                }
+                i += 1;
+            }
+        }
+        child
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimRng, SimulationConfig};
+
+    // Index of the parent each gene value was taken from, in gene order
+    fn gene_sources(child: &Genome, a: &Genome, b: &Genome) -> Vec<usize> {
+        Gene::ALL.iter().flat_map(|gene| {
+            child.gene(*gene).iter().zip(a.gene(*gene)).zip(b.gene(*gene))
+                .map(|((c, x), y)| if c == x { 0 } else { assert_eq!(c, y); 1 })
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn crossover_takes_every_gene_from_a_parent() {
+        let config = SimulationConfig { charge_types: 4, ..default() };
+        let mut rng = SimRng::from_seed(23);
+        // Parents differ in every gene value so each value reveals its source
+        let a = Genome::zeroed(4);
+        let mut b = Genome::zeroed(4);
+        for gene in Gene::ALL {
+            b.gene_mut(gene).fill(1);
+        }
+        for mode in [CrossoverMode::OnePoint, CrossoverMode::TwoPoint, CrossoverMode::Uniform] {
+            let mut saw_both = false;
+            for _ in 0..50 {
+                let child = Genome::crossover(&a, &b, mode, &mut rng);
+                let sources = gene_sources(&child, &a, &b);
+                let switches = sources.windows(2).filter(|w| w[0] != w[1]).count();
+                match mode {
+                    // The partner's genes form a single run, ending at the last gene for one point
+                    CrossoverMode::OnePoint => assert!(sources.windows(2).all(|w| w[0] <= w[1])),
+                    CrossoverMode::TwoPoint => assert!(switches <= 1 || switches == 2 && sources[0] == 0),
+                    _ => ()
+                }
+                saw_both |= sources.contains(&0) && sources.contains(&1);
+            }
+            assert!(saw_both, "{:?} never mixed the parents", mode);
+        }
+        let mut c = Genome::random(&config, &mut rng);
+        c.compatibility = a.compatibility + config.crossover.compatibility_tolerance;
+        assert!(a.compatible(&c, &config.crossover));
+        c.compatibility += 1;
+        assert!(!a.compatible(&c, &config.crossover));
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Deceleration of a cell moving at speed v is `linear * v + quadratic * v^2`, per
+// reference tick. With `scale_by_radius` those are forces proportional to the radius
+// instead, divided by the mass, so larger cells coast further.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DragConfig {
+    pub linear: f32,
+    pub quadratic: f32,
+    pub scale_by_radius: bool,
+    pub max_speed: Option<f32>  // Speeds above this are cut back after drag
+}
+
+impl Default for DragConfig {
+    fn default() -> Self {
+        // Loses a tenth of its speed per reference tick
+        Self {
+            linear: -(0.9f32).ln(),
+            quadratic: 0.,
+            scale_by_radius: false,
+            max_speed: None
+        }
+    }
+}
+
+impl DragConfig {
+
+    // Slows `vel` down over dt by solving the drag equation exactly, which can stop a cell
+    // but never turn it around however strong the drag
+    pub fn apply(&self, vel: &mut Vec2, radius: f32, mass: f32, dt: f32) {
+        let speed = vel.length();
+        if speed > 0. {
+            let k = if self.scale_by_radius { radius / mass } else { 1. };
+            let (a, b) = (self.linear * k, self.quadratic * k);
+            let slowed = if a > 0. {
+                let decay = (-a * dt).exp();
+                a * speed * decay / (a + b * speed * (1. - decay))
+            }
+            else {
+                speed / (1. + b * speed * dt)
+            };
+            *vel *= slowed / speed;
+        }
+        if let Some(max_speed) = self.max_speed {
+            *vel = vel.clamp_length_max(max_speed);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    #[test]
+    fn drag_slows_cells_down() {
+        // The default takes a tenth off per reference tick, however the tick is split
+        let drag = DragConfig::default();
+        let mut vel = Vec2::new(3., 4.);
+        drag.apply(&mut vel, 10., 300., 1.);
+        assert!((vel.length() - 4.5).abs() < 1e-4, "{}", vel.length());
+        let mut vel = Vec2::new(3., 4.);
+        for _ in 0..4 {
+            drag.apply(&mut vel, 10., 300., 0.25);
+        }
+        assert!((vel.length() - 4.5).abs() < 1e-4, "{}", vel.length());
+
+        // Strong drag stops a cell without turning it around
+        let drag = DragConfig { linear: 50., quadratic: 50., ..default() };
+        let mut vel = Vec2::new(1000., 0.);
+        drag.apply(&mut vel, 10., 300., 1.);
+        assert!(vel.x >= 0. && vel.x < 1e-3, "{}", vel.x);
+
+        // Quadratic drag alone keeps any starting speed within 1 / (quadratic * dt)
+        let drag = DragConfig { linear: 0., quadratic: 0.5, ..default() };
+        let mut vel = Vec2::new(0., 1e9);
+        drag.apply(&mut vel, 10., 300., 1.);
+        assert!(vel.y <= 2., "{}", vel.y);
+
+        // Larger cells lose less speed to radius scaled drag
+        let drag = DragConfig { scale_by_radius: true, ..default() };
+        let (mut small, mut large) = (Vec2::X, Vec2::X);
+        let (b_small, b_large) = (Body::new(0., 0., 50.), Body::new(0., 0., 2000.));
+        drag.apply(&mut small, b_small.radius(), b_small.mass, 1.);
+        drag.apply(&mut large, b_large.radius(), b_large.mass, 1.);
+        assert!(small.x < large.x && large.x < 1.);
+
+        // The cap applies on top of drag
+        let drag = DragConfig { linear: 0., max_speed: Some(2.), ..default() };
+        let mut vel = Vec2::new(30., 40.);
+        drag.apply(&mut vel, 10., 300., 1.);
+        assert!((vel - Vec2::new(1.2, 1.6)).length() < 1e-5, "{:?}", vel);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{fmt, fs, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{Body, Gene, Genome, CHARGE_COUNT};
+
+// Bump whenever the gene layout changes
+pub const GENOME_ENCODING_VERSION: u8 = 5;
+
+pub const GENOME_DUMP_HOTKEY: KeyCode = KeyCode::G;
+
+// Where dumped genomes are appended, in the format --genomes reads
+pub const GENOME_DUMP_PATH: &str = "genomes.txt";
+
+// Charge type count, scalar genes and interaction tables
+fn gene_bytes(charge_types: usize) -> usize {
+    2 + Gene::ALL.iter().map(|gene| if gene.is_table() { charge_types } else { 1 }).sum::<usize>()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GenomeDecodeError {
+    Base64,
+    Length(usize),
+    Version(u8),
+    ChargeTypes(usize),
+    Checksum
+}
+
+impl fmt::Display for GenomeDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenomeDecodeError::Base64 => write!(f, "not valid base64"),
+            GenomeDecodeError::Length(n) => write!(f, "unexpected length of {} bytes", n),
+            GenomeDecodeError::Version(v) => write!(f, "unsupported version {}, expected {}", v, GENOME_ENCODING_VERSION),
+            GenomeDecodeError::ChargeTypes(n) => write!(f, "for {} charge types, expected 1 to {}", n, CHARGE_COUNT),
+            GenomeDecodeError::Checksum => write!(f, "checksum mismatch")
+        }
+    }
+}
+
+impl std::error::Error for GenomeDecodeError {}
+
+impl Genome {
+
+    // URL safe base64 of a version byte, the genes and a CRC-32 of both
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(1 + gene_bytes(self.charge_types()) + 4);
+        bytes.push(GENOME_ENCODING_VERSION);
+        bytes.extend((self.charge_types() as u16).to_le_bytes());
+        for gene in Gene::ALL {
+            bytes.extend(self.gene(gene));
+        }
+        let checksum = crc32fast::hash(&bytes);
+        bytes.extend(checksum.to_le_bytes());
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(s: &str) -> Result<Self, GenomeDecodeError> {
+        let bytes = base64::decode_config(s.trim(), base64::URL_SAFE_NO_PAD).map_err(|_| GenomeDecodeError::Base64)?;
+        if bytes.is_empty() {
+            return Err(GenomeDecodeError::Length(0))
+        }
+        if bytes[0] != GENOME_ENCODING_VERSION {
+            return Err(GenomeDecodeError::Version(bytes[0]))
+        }
+        if bytes.len() < 3 {
+            return Err(GenomeDecodeError::Length(bytes.len()))
+        }
+        let n = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+        if bytes.len() != 1 + gene_bytes(n) + 4 {
+            return Err(GenomeDecodeError::Length(bytes.len()))
+        }
+        let (payload, checksum) = bytes.split_at(1 + gene_bytes(n));
+        if crc32fast::hash(payload).to_le_bytes() != checksum {
+            return Err(GenomeDecodeError::Checksum)
+        }
+        if !(1..=CHARGE_COUNT).contains(&n) {
+            return Err(GenomeDecodeError::ChargeTypes(n))
+        }
+        let mut genome = Self::zeroed(n);
+        let mut genes = &payload[3..];
+        for gene in Gene::ALL {
+            let values = genome.gene_mut(gene);
+            let (head, rest) = genes.split_at(values.len());
+            values.copy_from_slice(head);
+            genes = rest;
+        }
+        Ok(genome)
+    }
+
+}
+
+// Genomes to build the initial population from
+#[derive(Resource, Clone)]
+pub struct SeedGenomes(pub Vec<Genome>);
+
+impl SeedGenomes {
+
+    // One encoded genome per line. Blank lines and lines starting with # are skipped.
+    pub fn load(path: &Path, charge_types: usize) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read genomes {}: {}", path.display(), e))?;
+        let mut genomes = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+            let genome = Genome::decode(line).map_err(|e| format!("{}:{}: genome is {}", path.display(), n + 1, e))?;
+            if genome.charge_types() != charge_types {
+                return Err(format!("{}:{}: genome has {} charge types, the config has {}", path.display(), n + 1, genome.charge_types(), charge_types))
+            }
+            genomes.push(genome);
+        }
+        if genomes.is_empty() {
+            return Err(format!("{} contains no genomes", path.display()))
+        }
+        Ok(Self(genomes))
+    }
+
+}
+
+// Prints the genome of the cell under the cursor and appends it to GENOME_DUMP_PATH
+pub fn genome_dump_system(
+    keys: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    q_cells: Query<(&Body, &Genome)>
+) {
+    if !keys.just_pressed(GENOME_DUMP_HOTKEY) { return }
+    let Some(cursor) = windows.get_primary().and_then(|w| w.cursor_position()) else { return };
+    let Some(cursor) = q_camera.iter().find_map(|(camera, transform)| camera.viewport_to_world(transform, cursor)) else { return };
+    let cursor = cursor.origin.truncate();
+    let hit = q_cells.iter()
+        .filter(|(body, _)| body.pos.distance(cursor) <= body.radius())
+        .min_by(|(a, _), (b, _)| a.pos.distance(cursor).total_cmp(&b.pos.distance(cursor)));
+    if let Some((_, genome)) = hit {
+        let encoded = genome.encode();
+        info!("{}", encoded);
+        let result = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(GENOME_DUMP_PATH)
+            .and_then(|mut f| std::io::Write::write_all(&mut f, format!("{}\n", encoded).as_bytes()));
+        if let Err(e) = result {
+            error!("Failed to append genome to {}: {}", GENOME_DUMP_PATH, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimRng, SimulationConfig};
+
+    #[test]
+    fn max_charge_survives_encoding() {
+        let mut g = Genome::random(&SimulationConfig::default(), &mut SimRng::from_seed(3));
+        g.charge = u8::MAX;
+        g.force_range[u8::MAX as usize] = u8::MAX;
+        let decoded = Genome::decode(&g.encode()).unwrap();
+        assert_eq!(decoded.charge, u8::MAX);
+        assert_eq!(decoded.force_range, g.force_range);
+    }
+
+    #[test]
+    fn genomes_encode_any_charge_type_count() {
+        for charge_types in [1, 4, CHARGE_COUNT] {
+            let config = SimulationConfig { charge_types, ..default() };
+            let g = Genome::random(&config, &mut SimRng::from_seed(9));
+            let decoded = Genome::decode(&g.encode()).unwrap();
+            assert_eq!(decoded.charge_types(), charge_types);
+            assert_eq!(decoded.eat_rate, g.eat_rate);
+        }
+    }
+
+    fn bytes(g: &Genome) -> Vec<u8> {
+        base64::decode_config(g.encode(), base64::URL_SAFE_NO_PAD).unwrap()
+    }
+
+    // Encodes a payload with a valid checksum appended
+    fn with_checksum(payload: &[u8]) -> String {
+        let mut bytes = payload.to_vec();
+        bytes.extend(crc32fast::hash(payload).to_le_bytes());
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn damaged_genomes_fail_to_decode() {
+        let g = Genome::random(&SimulationConfig::default(), &mut SimRng::from_seed(5));
+        let mut flipped = bytes(&g);
+        flipped[10] ^= 1;
+        assert_eq!(Genome::decode(&base64::encode_config(flipped, base64::URL_SAFE_NO_PAD)).err(), Some(GenomeDecodeError::Checksum));
+
+        let mut versioned = bytes(&g);
+        versioned[0] = GENOME_ENCODING_VERSION + 1;
+        let payload_len = versioned.len() - 4;
+        let encoded = with_checksum(&versioned[..payload_len]);
+        assert_eq!(Genome::decode(&encoded).err(), Some(GenomeDecodeError::Version(GENOME_ENCODING_VERSION + 1)));
+
+        let encoded = g.encode();
+        let truncated = &encoded[..encoded.len() - 8];
+        assert_eq!(Genome::decode(truncated).err(), Some(GenomeDecodeError::Length(bytes(&g).len() - 6)));
+        assert_eq!(Genome::decode("").err(), Some(GenomeDecodeError::Length(0)));
+        assert_eq!(Genome::decode("not base64!").err(), Some(GenomeDecodeError::Base64));
+    }
+
+    #[test]
+    fn charge_type_counts_out_of_range_fail_to_decode() {
+        for n in [0, CHARGE_COUNT + 1] {
+            let mut payload = vec![GENOME_ENCODING_VERSION];
+            payload.extend((n as u16).to_le_bytes());
+            payload.resize(1 + gene_bytes(n), 0);
+            assert_eq!(Genome::decode(&with_checksum(&payload)).err(), Some(GenomeDecodeError::ChargeTypes(n)));
+        }
+    }
+
+    #[test]
+    fn seed_genomes_skip_comments_and_check_charge_types() {
+        let mut rng = SimRng::from_seed(6);
+        let a = Genome::random(&SimulationConfig::default(), &mut rng);
+        let b = Genome::random(&SimulationConfig::default(), &mut rng);
+        let path = std::env::temp_dir().join(format!("genomes-{}.txt", std::process::id()));
+        fs::write(&path, format!("# seeds\n\n  {}\n\n# more\n{}\n", a.encode(), b.encode())).unwrap();
+        let charge_types = a.charge_types();
+        let loaded = SeedGenomes::load(&path, charge_types);
+        let mismatch = SeedGenomes::load(&path, charge_types + 1);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.0.len(), 2);
+        assert_eq!((loaded.0[0].encode(), loaded.0[1].encode()), (a.encode(), b.encode()));
+        let e = mismatch.err().unwrap();
+        assert!(e.ends_with(&format!(":3: genome has {} charge types, the config has {}", charge_types, charge_types + 1)), "{}", e);
+    }
+}
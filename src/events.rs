@@ -0,0 +1,152 @@
+use std::{fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}, str::FromStr};
+
+use bevy::prelude::*;
+use serde::{Serialize, Serializer};
+
+// Why a cell was removed from the world
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeathCause {
+    Starved,  // Shrank below the minimum size
+    Eaten  // Consumed whole by a predator
+}
+
+// A cell entered the world, at startup, from a snapshot or by division
+#[derive(Clone, Debug, Serialize)]
+pub struct CellSpawnedEvent {
+    #[serde(skip)]
+    pub tick: u64,  // Frames can run several ticks, so events carry their own. Log lines have it up front.
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub generation: u32,
+    #[serde(serialize_with = "vec2")]
+    pub pos: Vec2,
+    pub mass: f32
+}
+
+// A cell split in two. Its daughters follow as CellSpawnedEvents.
+#[derive(Clone, Debug, Serialize)]
+pub struct CellDividedEvent {
+    #[serde(skip)]
+    pub tick: u64,
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub generation: u32,
+    #[serde(serialize_with = "vec2")]
+    pub pos: Vec2,
+    pub mass: f32
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CellDiedEvent {
+    #[serde(skip)]
+    pub tick: u64,
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub generation: u32,
+    pub cause: DeathCause,
+    #[serde(serialize_with = "vec2")]
+    pub pos: Vec2,
+    pub mass: f32
+}
+
+// Which events the event log keeps
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventLevel {
+    #[default]
+    Info,  // Deaths and divisions
+    Debug  // Spawns as well
+}
+
+impl FromStr for EventLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(EventLevel::Info),
+            "debug" => Ok(EventLevel::Debug),
+            _ => Err(format!("Unknown event level {}, expected info or debug", s))
+        }
+    }
+}
+
+// Writes cell events at or below `level` to a file, one JSON object per line
+pub struct EventLogPlugin {
+    pub path: PathBuf,
+    pub level: EventLevel
+}
+
+#[derive(Resource)]
+pub struct EventLog {
+    out: BufWriter<File>,
+    level: EventLevel
+}
+
+#[derive(Serialize)]
+struct EventLine<'a, E: Serialize> {
+    tick: u64,
+    event: &'static str,
+    #[serde(flatten)]
+    data: &'a E
+}
+
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        let log = match EventLog::create(&self.path, self.level) {
+            Ok(log) => log,
+            Err(e) => {
+                error!("{}, not logging events", e);
+                return
+            }
+        };
+        app.insert_resource(log)
+            .add_system_to_stage(CoreStage::PostUpdate, event_log_system);
+    }
+}
+
+impl EventLog {
+
+    pub fn create(path: &Path, level: EventLevel) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create event log {}: {}", path.display(), e))?;
+        Ok(Self { out: BufWriter::new(file), level })
+    }
+
+    fn write(&mut self, line: &str) {
+        if let Err(e) = writeln!(self.out, "{}", line) {
+            error!("Failed to write event log: {}", e);
+        }
+    }
+
+}
+
+fn line<E: Serialize>(tick: u64, event: &'static str, data: &E) -> String {
+    serde_json::to_string(&EventLine { tick, event, data }).unwrap()
+}
+
+// A frame can run several ticks. Within a tick cells spawn, then divide, then die, so a
+// stable sort by tick puts the frame's events back in the order they happened.
+fn event_log_system(
+    mut log: ResMut<EventLog>,
+    mut er_spawned: EventReader<CellSpawnedEvent>,
+    mut er_divided: EventReader<CellDividedEvent>,
+    mut er_died: EventReader<CellDiedEvent>
+) {
+    let debug = log.level >= EventLevel::Debug;
+    let mut lines: Vec<(u64, String)> = er_spawned.iter()
+        .filter(|_| debug)
+        .map(|e| (e.tick, line(e.tick, "spawned", e)))
+        .chain(er_divided.iter().map(|e| (e.tick, line(e.tick, "divided", e))))
+        .chain(er_died.iter().map(|e| (e.tick, line(e.tick, "died", e))))
+        .collect();
+    lines.sort_by_key(|(tick, _)| *tick);
+    for (_, line) in lines.iter() {
+        log.write(line);
+    }
+    if let Err(e) = log.out.flush() {
+        error!("Failed to write event log: {}", e);
+    }
+}
+
+fn vec2<S: Serializer>(v: &Vec2, serializer: S) -> Result<S::Ok, S::Error> {
+    v.to_array().serialize(serializer)
+}
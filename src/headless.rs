@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    time::TimeUpdateStrategy
+};
+
+use crate::{Body, SimulationTick};
+
+// Runs the simulation without a window or renderer. Time advances by `frame_dt` per
+// update regardless of how long the update took, so runs don't depend on the host.
+pub struct HeadlessPlugin {
+    pub frame_dt: Duration,
+    pub max_ticks: Option<u64>,
+    pub max_wall_time: Option<Duration>
+}
+
+#[derive(Resource)]
+pub struct HeadlessRun {
+    pub frame_dt: Duration,
+    pub max_ticks: Option<u64>,
+    pub max_wall_time: Option<Duration>,
+    started: Instant,
+    clock: Instant
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let now = Instant::now();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualInstant(now))
+            .insert_resource(HeadlessRun {
+                frame_dt: self.frame_dt,
+                max_ticks: self.max_ticks,
+                max_wall_time: self.max_wall_time,
+                started: now,
+                clock: now
+            })
+            .add_system_to_stage(CoreStage::Last, virtual_clock_system)
+            .add_system_to_stage(CoreStage::PostUpdate, run_budget_system);
+    }
+}
+
+// Schedules the instant the next update will see
+fn virtual_clock_system(
+    mut run: ResMut<HeadlessRun>,
+    mut strategy: ResMut<TimeUpdateStrategy>
+) {
+    let frame_dt = run.frame_dt;
+    run.clock += frame_dt;
+    *strategy = TimeUpdateStrategy::ManualInstant(run.clock);
+}
+
+fn run_budget_system(
+    run: Res<HeadlessRun>,
+    tick: Res<SimulationTick>,
+    time: Res<Time>,
+    query: Query<&Body>,
+    mut ew_exit: EventWriter<AppExit>
+) {
+    let out_of_ticks = run.max_ticks.is_some_and(|max| tick.0 >= max);
+    let out_of_time = run.max_wall_time.is_some_and(|max| run.started.elapsed() >= max);
+    if out_of_ticks || out_of_time {
+        let (population, total_mass) = query.iter().fold((0, 0.), |(n, m), body| (n + 1, m + body.mass));
+        info!(
+            "Headless run finished after {} ticks ({:.1}s simulated, {:.1}s wall): {} cells, total mass {:.1}",
+            tick.0,
+            time.elapsed_seconds(),
+            run.started.elapsed().as_secs_f32(),
+            population,
+            total_mass
+        );
+        ew_exit.send(AppExit);
+    }
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// How positions and velocities are advanced from the accelerations of a tick
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    #[default]
+    SemiImplicitEuler,  // Velocity first, then position with the new velocity
+    VelocityVerlet  // Position from the current acceleration, velocity from the average of the last two
+}
+
+// Acceleration on a cell from its neighbours, written by the force system each tick
+#[derive(Component, Clone, Copy, Default)]
+pub struct Acceleration {
+    pub acc: Vec2,
+    pub previous: Option<Vec2>  // Last tick's, which velocity Verlet needs to finish its velocity update
+}
+
+impl Integrator {
+
+    // Advances one tick of length dt given the acceleration at the current position
+    pub fn step(self, pos: &mut Vec2, vel: &mut Vec2, acceleration: &mut Acceleration, dt: f32) {
+        let acc = acceleration.acc;
+        match self {
+            Integrator::SemiImplicitEuler => {
+                *vel += acc * dt;
+                *pos += *vel * dt;
+            }
+            Integrator::VelocityVerlet => {
+                // A new cell has no previous acceleration, its velocity is already current
+                if let Some(previous) = acceleration.previous {
+                    *vel += (previous + acc) * 0.5 * dt;
+                }
+                *pos += *vel * dt + acc * 0.5 * dt * dt;
+            }
+        }
+        acceleration.previous = Some(acc);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Largest relative energy error of two unit masses on a frictionless spring, and the mean
+    // error over the first and last tenth of the run to tell oscillation from drift
+    fn two_body_energy_error(integrator: Integrator, ticks: usize) -> (f32, f32, f32) {
+        let k = 0.01;
+        let mut pos = [Vec2::new(-10., 0.), Vec2::new(10., 0.)];
+        let mut vel = [Vec2::new(0., 0.5), Vec2::new(0., -0.5)];
+        let mut acc = [Acceleration::default(); 2];
+        let energy = |pos: &[Vec2; 2], vel: &[Vec2; 2]| {
+            0.5 * (vel[0].length_squared() + vel[1].length_squared()) + 0.5 * k * pos[0].distance_squared(pos[1])
+        };
+        let e0 = energy(&pos, &vel);
+        let mut errors = Vec::with_capacity(ticks);
+        for _ in 0..ticks {
+            acc[0].acc = (pos[1] - pos[0]) * k;
+            acc[1].acc = (pos[0] - pos[1]) * k;
+            for i in 0..2 {
+                integrator.step(&mut pos[i], &mut vel[i], &mut acc[i], 1.);
+            }
+            // Verlet's velocity is only current once the next tick's acceleration is known
+            let mut v = vel;
+            if integrator == Integrator::VelocityVerlet {
+                let a = (pos[1] - pos[0]) * k;
+                v[0] += (a + acc[0].acc) * 0.5;
+                v[1] += (-a + acc[1].acc) * 0.5;
+            }
+            errors.push((energy(&pos, &v) - e0).abs() / e0);
+        }
+        let tenth = ticks / 10;
+        let mean = |e: &[f32]| e.iter().sum::<f32>() / e.len() as f32;
+        (errors.iter().cloned().fold(0., f32::max), mean(&errors[..tenth]), mean(&errors[ticks - tenth..]))
+    }
+
+    #[test]
+    fn integrators_conserve_two_body_energy() {
+        let (euler, euler_start, euler_end) = two_body_energy_error(Integrator::SemiImplicitEuler, 20000);
+        let (verlet, verlet_start, verlet_end) = two_body_energy_error(Integrator::VelocityVerlet, 20000);
+        assert!(euler < 0.15, "semi-implicit Euler energy error {}", euler);
+        assert!(verlet < 0.01, "velocity Verlet energy error {}", verlet);
+        assert!(verlet * 10. < euler, "velocity Verlet ({}) isn't more accurate than semi-implicit Euler ({})", verlet, euler);
+        // Both are symplectic, the error oscillates instead of growing
+        assert!(euler_end < euler_start * 1.5, "semi-implicit Euler drifted from {} to {}", euler_start, euler_end);
+        assert!(verlet_end < verlet_start * 1.5, "velocity Verlet drifted from {} to {}", verlet_start, verlet_end);
+    }
+}
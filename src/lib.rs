@@ -0,0 +1,1516 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy::sprite::ColorMaterial;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[allow(dead_code)]
+const SCALE_FACTOR: f32 = 1.0;
+
+const N_PARTICLES: u32 = 3;
+
+#[allow(clippy::approx_constant)]
+const PI: f32 = 3.14159;
+
+mod boundary;
+mod clock;
+mod config;
+mod crossover;
+mod drag;
+mod encoding;
+mod events;
+mod headless;
+mod integrator;
+mod lineage;
+mod metabolism;
+mod mutation;
+mod nutrients;
+mod predation;
+pub mod quadtree;
+mod rng;
+mod snapshot;
+mod species;
+mod stats;
+use boundary::*;
+use clock::*;
+use crossover::*;
+use encoding::*;
+use events::*;
+use integrator::*;
+use lineage::*;
+use metabolism::*;
+use nutrients::*;
+use predation::*;
+use quadtree::*;
+use rng::*;
+use snapshot::*;
+use species::*;
+use stats::*;
+
+// What an embedding app works with
+pub use boundary::BoundaryMode;
+pub use clock::{PhysicsStep, PhysicsSystem, SimulationClock};
+pub use config::{ConfigError, GeneRange, SimulationConfig};
+pub use crossover::{CrossoverConfig, CrossoverMode};
+pub use drag::DragConfig;
+pub use encoding::{GenomeDecodeError, SeedGenomes};
+pub use events::{CellDiedEvent, CellDividedEvent, CellSpawnedEvent, DeathCause, EventLevel};
+pub use headless::{HeadlessPlugin, HeadlessRun};
+pub use integrator::Integrator;
+pub use metabolism::{BiomassLedger, MetabolismConfig};
+pub use mutation::{MutationConfig, MutationOperator};
+pub use nutrients::{NutrientConfig, NutrientField};
+pub use rng::SimRng;
+pub use snapshot::{CellSnapshot, Snapshot};
+pub use species::{Species, SpeciesConfig, SpeciesRegistry};
+pub use stats::StatsConfig;
+
+const EPSILON: f32 = 0.000000000000000001;
+
+#[allow(clippy::excessive_precision)]
+const INV_255: f32 = 0.00392156862745098;
+
+// Number of distinct charges, one per u8 value, and so the most charge types a config can ask for
+const CHARGE_COUNT: usize = u8::MAX as usize + 1;
+
+fn random_u8_vec(n: usize, rng: &mut impl Rng) -> Vec<u8> {
+    let mut c = vec![0; n];
+    rng.fill(&mut c[..]);
+    c
+}
+
+#[inline(always)]
+fn u8_to_range(i: u8, minimum: f32, maximum: f32) -> f32 {
+    // println!("Decoded u8 {} as {}", i as f32, i as f32 * (INV_255 * maximum - INV_255 * minimum) + minimum);
+    if maximum <= minimum { return minimum }
+    ((i as f32) * (INV_255 * maximum - INV_255 * minimum) + minimum).clamp(minimum, maximum)
+}
+    
+#[inline(always)]
+fn range_to_u8(minimum: f32, maximum: f32, v: f32) -> u8 {
+    // println!("Encoded f32 {} as {}", v, ((255. * (v - minimum)) / (maximum - minimum)).round() as u8);
+    if maximum == minimum { return 0 }
+    ((255. * (v - minimum)) / (maximum - minimum)).round().clamp(0., 255.) as u8
+}
+
+#[inline(always)]
+fn force(
+    x: f32,
+    repulsion_range: f32,
+    repulsion_strength: f32,
+    force_range: f32,
+    force_strength: f32
+) -> f32 {
+    if x > force_range + repulsion_range {
+        0.
+    }
+    else if x > repulsion_range {
+        force_strength * (1. - repulsion_range + force_range * 0.5 - x).abs() / (force_range * 0.5)
+    }
+    else {
+        -repulsion_strength * (repulsion_range - x) / repulsion_range
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CellSpawnEvent {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub size: f32,
+    pub genome: Genome,
+    pub parent: Option<u32>,  // Id of the cell this one divided from
+    pub generation: u32,
+    pub species: Option<u32>  // The parent's, until the next clustering pass places the cell
+}
+
+// Every gene of a Genome, in encoding order. Code that walks all the genes goes through
+// Gene::ALL and Genome::gene_mut so a new gene only has to be registered here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gene {
+    Charge,
+    DivisionProb,
+    DivisionAsym,
+    DivisionMinSize,
+    RepulsionRange,
+    RepulsionStrength,
+    ForceRange,
+    ForceStrength,
+    EatRate,
+    Uptake,
+    Compatibility
+}
+
+impl Gene {
+
+    pub const ALL: [Gene; 11] = [
+        Gene::Charge,
+        Gene::DivisionProb,
+        Gene::DivisionAsym,
+        Gene::DivisionMinSize,
+        Gene::RepulsionRange,
+        Gene::RepulsionStrength,
+        Gene::ForceRange,
+        Gene::ForceStrength,
+        Gene::EatRate,
+        Gene::Uptake,
+        Gene::Compatibility
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Gene::Charge => "charge",
+            Gene::DivisionProb => "division_prob",
+            Gene::DivisionAsym => "division_asym",
+            Gene::DivisionMinSize => "division_min_size",
+            Gene::RepulsionRange => "repulsion_range",
+            Gene::RepulsionStrength => "repulsion_strength",
+            Gene::ForceRange => "force_range",
+            Gene::ForceStrength => "force_strength",
+            Gene::EatRate => "eat_rate",
+            Gene::Uptake => "uptake",
+            Gene::Compatibility => "compatibility"
+        }
+    }
+
+    // Per charge type interaction table rather than a single value
+    pub fn is_table(self) -> bool {
+        matches!(self, Gene::RepulsionRange | Gene::RepulsionStrength | Gene::ForceRange | Gene::ForceStrength | Gene::EatRate)
+    }
+
+}
+
+// The interaction tables hold one entry per charge type. Charges are binned evenly into
+// however many types the tables have, so genomes only interact through their types.
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct Genome {
+    charge: u8,
+    division_prob: u8,
+    division_asym: u8,
+    division_min_size: u8,
+    repulsion_range: Vec<u8>,
+    repulsion_strength: Vec<u8>,
+    force_range: Vec<u8>,
+    force_strength: Vec<u8>,
+    eat_rate: Vec<u8>,
+    uptake: u8,  // How fast the cell draws on the nutrient field
+    compatibility: u8  // Cells only mate with partners whose value is close to their own
+}
+
+impl Genome {
+    #[allow(dead_code)]
+    fn new(config: &SimulationConfig) -> Self {
+        let n = config.charge_types;
+        Self {
+            charge: 1,
+            division_prob: config.division_prob.encode(config.division_prob.midpoint()),
+            division_asym: config.division_asym.encode(config.division_asym.midpoint()),
+            division_min_size: config.division_min_size.encode(config.division_min_size.midpoint()),
+            repulsion_range: vec![config.repulsion_range.encode(config.repulsion_range.midpoint()); n],
+            repulsion_strength: vec![config.repulsion_strength.encode(config.repulsion_strength.midpoint()); n],
+            force_range: vec![config.force_range.encode(config.force_range.midpoint()); n],
+            force_strength: vec![config.force_strength.encode(config.force_strength.max); n],
+            eat_rate: vec![config.eat_rate.encode(config.eat_rate.midpoint()); n],
+            uptake: config.uptake.encode(config.uptake.midpoint()),
+            compatibility: u8::MAX / 2
+        }
+    }
+
+    pub fn random(config: &SimulationConfig, rng: &mut impl Rng) -> Self {
+        let n = config.charge_types;
+        Self {
+            charge: rng.gen(),
+            division_prob: rng.gen(),
+            division_asym: rng.gen(),
+            division_min_size: rng.gen(),
+            repulsion_range: random_u8_vec(n, rng),
+            repulsion_strength: random_u8_vec(n, rng),
+            force_range: random_u8_vec(n, rng),
+            force_strength: random_u8_vec(n, rng),
+            eat_rate: random_u8_vec(n, rng),
+            uptake: rng.gen(),
+            compatibility: rng.gen()
+        }
+    }
+
+    // Number of charge types the interaction tables are sized for
+    fn charge_types(&self) -> usize {
+        self.force_range.len()
+    }
+
+    // Index into this genome's tables for a cell with the given charge
+    #[inline(always)]
+    fn charge_type(&self, charge: u8) -> usize {
+        charge as usize * self.charge_types() / CHARGE_COUNT
+    }
+
+    // All zero genes with tables for n charge types
+    fn zeroed(n: usize) -> Self {
+        Self {
+            charge: 0,
+            division_prob: 0,
+            division_asym: 0,
+            division_min_size: 0,
+            repulsion_range: vec![0; n],
+            repulsion_strength: vec![0; n],
+            force_range: vec![0; n],
+            force_strength: vec![0; n],
+            eat_rate: vec![0; n],
+            uptake: 0,
+            compatibility: 0
+        }
+    }
+
+    // The value of a gene, a single byte for scalar genes
+    fn gene(&self, gene: Gene) -> &[u8] {
+        match gene {
+            Gene::Charge => std::slice::from_ref(&self.charge),
+            Gene::DivisionProb => std::slice::from_ref(&self.division_prob),
+            Gene::DivisionAsym => std::slice::from_ref(&self.division_asym),
+            Gene::DivisionMinSize => std::slice::from_ref(&self.division_min_size),
+            Gene::RepulsionRange => &self.repulsion_range,
+            Gene::RepulsionStrength => &self.repulsion_strength,
+            Gene::ForceRange => &self.force_range,
+            Gene::ForceStrength => &self.force_strength,
+            Gene::EatRate => &self.eat_rate,
+            Gene::Uptake => std::slice::from_ref(&self.uptake),
+            Gene::Compatibility => std::slice::from_ref(&self.compatibility)
+        }
+    }
+
+    fn gene_mut(&mut self, gene: Gene) -> &mut [u8] {
+        match gene {
+            Gene::Charge => std::slice::from_mut(&mut self.charge),
+            Gene::DivisionProb => std::slice::from_mut(&mut self.division_prob),
+            Gene::DivisionAsym => std::slice::from_mut(&mut self.division_asym),
+            Gene::DivisionMinSize => std::slice::from_mut(&mut self.division_min_size),
+            Gene::RepulsionRange => &mut self.repulsion_range,
+            Gene::RepulsionStrength => &mut self.repulsion_strength,
+            Gene::ForceRange => &mut self.force_range,
+            Gene::ForceStrength => &mut self.force_strength,
+            Gene::EatRate => &mut self.eat_rate,
+            Gene::Uptake => std::slice::from_mut(&mut self.uptake),
+            Gene::Compatibility => std::slice::from_mut(&mut self.compatibility)
+        }
+    }
+
+    fn mutate_from(genome: &Genome, config: &SimulationConfig, rng: &mut impl Rng) -> Genome {
+        let mut g = genome.clone();
+        let p = config.evolution_probability;
+        if p > rng.gen_range(0.0..1.0) {
+            for gene in Gene::ALL {
+                if p > rng.gen_range(0.0..1.0) {
+                    // Each entry of a table mutates independently
+                    let is_table = gene.is_table();
+                    let operator = config.mutation.operator(gene);
+                    for v in g.gene_mut(gene).iter_mut() {
+                        if !is_table || p > rng.gen_range(0.0..1.0) {
+                            *v = operator.apply(*v, rng);
+                        }
+                    }
+                }
+            }
+        }
+        g
+    }
+
+}
+
+#[derive(Component)]
+pub struct Cell {
+    pub id: u32,  // Unique within a run, carried over by snapshots
+    pub parent: Option<u32>,
+    pub generation: u32
+    // division_timer: Timer
+}
+
+impl Cell {
+    pub fn new(id: u32, parent: Option<u32>, generation: u32) -> Self {
+        Self {
+            id,
+            parent,
+            generation
+            // division_timer = Timer
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Velocity {
+    pub vel: Vec2
+}
+
+impl Velocity {
+    pub fn new(dx: f32, dy: f32) -> Self {
+        Self {
+            vel: Vec2::new(dx, dy)
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Body {
+    pub pos: Vec2,
+    pub mass: f32
+}
+
+impl Body {
+
+    pub fn new(x: f32, y: f32, mass: f32) -> Self {
+        Self { pos: Vec2::new(x, y), mass }
+    }
+
+    pub fn radius(&self) -> f32 {
+        (self.mass / PI).sqrt()
+    }
+
+}
+
+// Extent of the simulated area, centered on the origin
+#[derive(Resource, Clone, Copy)]
+pub struct WorldBounds {
+    pub width: f32,
+    pub height: f32
+}
+
+// Number of simulation ticks since the run started
+#[derive(Resource, Default)]
+pub struct SimulationTick(pub u64);
+
+// The whole simulation, for the binary or any other Bevy app to add. Headless runs need
+// HeadlessPlugin added alongside, windowed ones DefaultPlugins.
+pub struct GeneticParticlesPlugin {
+    pub config: SimulationConfig,
+    pub snapshot: Option<Snapshot>,  // Resumed in place of a random population
+    pub options: SimulationOptions
+}
+
+// How a run starts, what it records and whether it is drawn. The binary fills these in
+// from the command line.
+#[derive(Clone, Default)]
+pub struct SimulationOptions {
+    pub seed: Option<u64>,  // Random unless given, or the snapshot's when resuming
+    pub seed_genomes: Option<SeedGenomes>,  // The initial population cycles through these
+    pub lineage: Option<PathBuf>,  // Write the lineage here on exit
+    pub species_log: Option<PathBuf>,  // Write species member counts over time here on exit
+    pub stats: Option<PathBuf>,  // Record population statistics here while running
+    pub event_log: Option<PathBuf>,  // Write cell events here as JSON lines
+    pub event_level: EventLevel,
+    pub save_snapshot: Option<PathBuf>,  // Write a snapshot here on exit
+    pub draw: bool  // Render the cells and take hotkeys, which needs DefaultPlugins
+}
+
+impl Plugin for GeneticParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        let (options, config) = (&self.options, self.config.clone());
+        let rng = match (options.seed, &self.snapshot) {
+            (Some(seed), _) => SimRng::from_seed(seed),
+            (None, Some(snapshot)) => snapshot.rng.clone(),
+            (None, None) => SimRng::from_entropy()
+        };
+        info!("Seed: {}", rng.seed());
+        let clock = SimulationClock::new(config.ticks_per_second);
+        app.insert_resource(WorldBounds { width: config.world_width, height: config.world_height })
+            .insert_resource(config)
+            .insert_resource(rng);
+        if let Some(snapshot) = &self.snapshot {
+            app.insert_resource(snapshot.clone())
+                .add_startup_system(restore_snapshot_system);
+        }
+        else {
+            if let Some(genomes) = &options.seed_genomes {
+                app.insert_resource(genomes.clone());
+            }
+            app.add_startup_system(setup);
+        }
+        if let Some(path) = &options.lineage {
+            app.init_resource::<LineageLog>()
+                .insert_resource(LineageOnExit(path.clone()))
+                .add_system_to_stage(CoreStage::PostUpdate, lineage_system)
+                .add_system_to_stage(CoreStage::Last, lineage_on_exit_system);
+        }
+        if let Some(path) = &options.event_log {
+            app.add_plugin(EventLogPlugin { path: path.clone(), level: options.event_level });
+        }
+        if let Some(path) = &options.species_log {
+            app.init_resource::<SpeciesHistory>()
+                .insert_resource(SpeciesLogOnExit(path.clone()))
+                .add_system_to_stage(CoreStage::Last, species_log_on_exit_system);
+        }
+        if let Some(path) = &options.stats {
+            app.add_plugin(StatsPlugin { path: path.clone() });
+        }
+        if let Some(path) = &options.save_snapshot {
+            app.insert_resource(SnapshotOnExit(path.clone()))
+                .add_system_to_stage(CoreStage::Last, snapshot_on_exit_system);
+        }
+        if options.draw {
+            app.add_startup_system_to_stage(StartupStage::PreStartup, window_bounds_system)
+                .add_startup_system(setup_camera)
+                .add_startup_system(setup_species_legend)
+                .add_system_to_stage(CoreStage::PreUpdate, window_bounds_system)
+                // Input and drawing see the world as the frame's last tick left it
+                .add_system(cell_sprite_system.after(PhysicsSystem::Death))
+                .add_system(snapshot_hotkey_system.after(PhysicsSystem::Death))
+                .add_system(genome_dump_system.after(PhysicsSystem::Death))
+                .add_system(nutrient_heatmap_system.after(PhysicsSystem::Death))
+                .add_system(time_scale_hotkey_system.after(PhysicsSystem::Death))
+                .add_system(species_color_system.after(cell_sprite_system))
+                .add_system(species_legend_system.after(PhysicsSystem::Death))
+                .add_system_to_stage(CoreStage::PostUpdate, render_sync_system);
+        }
+        app.init_resource::<SimulationTick>()
+            .init_resource::<NextCellId>()
+            .init_resource::<SpeciesRegistry>()
+            .init_resource::<BiomassLedger>()
+            .init_resource::<Contacts>()
+            .init_resource::<Eaten>()
+            .insert_resource(clock)
+            // Drained by the next tick rather than cleared every update, so none get lost while paused
+            .init_resource::<Events<CellSpawnEvent>>()
+            .add_event::<CellSpawnedEvent>()
+            .add_event::<CellDividedEvent>()
+            .add_event::<CellDiedEvent>()
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(physics_step.label(PhysicsStep))
+                    .with_system(cell_spawn_system.label(PhysicsSystem::Spawn))
+                    .with_system(intercell_force_system.label(PhysicsSystem::Forces).after(PhysicsSystem::Spawn))
+                    .with_system(predation_system.label(PhysicsSystem::Predation).after(PhysicsSystem::Forces))
+                    .with_system(motion_system.label(PhysicsSystem::Integrate).after(PhysicsSystem::Predation))
+                    .with_system(metabolism_system.label(PhysicsSystem::Metabolism).after(PhysicsSystem::Integrate))
+                    .with_system(nutrient_field_system.label(PhysicsSystem::Forage).after(PhysicsSystem::Metabolism))
+                    .with_system(forage_system.label(PhysicsSystem::Forage).after(nutrient_field_system))
+                    .with_system(cell_death_system.label(PhysicsSystem::Death).after(PhysicsSystem::Divide))
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(RunCriteria::pipe(PhysicsStep, division_step))
+                    .with_system(division_system.label(PhysicsSystem::Divide).after(PhysicsSystem::Forage))
+            )
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_nutrient_field)
+            .add_system_to_stage(CoreStage::PostUpdate, biomass_ledger_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(RunCriteria::pipe(PhysicsStep, species_step))
+                    .with_system(species_system.before(PhysicsSystem::Spawn))
+            )
+            .add_system_to_stage(CoreStage::Last, biomass_report_system);
+    }
+}
+
+fn window_bounds_system(
+    windows: Res<Windows>,
+    mut bounds: ResMut<WorldBounds>
+) {
+    if let Some(window) = windows.get_primary() {
+        if bounds.width != window.width() || bounds.height != window.height() {
+            bounds.width = window.width();
+            bounds.height = window.height();
+        }
+    }
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+}
+
+fn setup(
+    bounds: Res<WorldBounds>,
+    config: Res<SimulationConfig>,
+    mut rng: ResMut<SimRng>,
+    seed_genomes: Option<Res<SeedGenomes>>,
+    mut ew_spawn: EventWriter<CellSpawnEvent>
+) {
+    // Cycle through the seed genomes if there are any, using each at least once
+    let n = seed_genomes.as_ref().map_or(0, |g| g.0.len()).max(N_PARTICLES as usize);
+
+    // Spawn some particles
+    for i in 0..n {
+
+        let h = bounds.height / 4.;
+        let w = bounds.width / 4.;
+
+        let x2 = rng.gen_range(-w..w);
+        let y2 = rng.gen_range(-h..h);
+
+
+        ew_spawn.send(
+            CellSpawnEvent {
+                size: rng.gen_range(2000.0..2400.0),
+                pos: Vec2::new(x2, y2),
+                vel: Vec2::new(0., 0.),
+                // genome: g
+                genome: match &seed_genomes {
+                    Some(genomes) => genomes.0[i % genomes.0.len()].clone(),
+                    None => Genome::random(&config, rng.as_mut())
+                },
+                parent: None,
+                generation: 0,
+                species: None
+            }
+        )
+    }
+}
+
+fn cell_spawn_system(
+    mut commands: Commands,
+    mut requests: ResMut<Events<CellSpawnEvent>>,
+    mut rng: ResMut<SimRng>,
+    mut next_id: ResMut<NextCellId>,
+    mut ew_spawned: EventWriter<CellSpawnedEvent>,
+    tick: Res<SimulationTick>
+) {
+    for e in requests.drain() {
+        let id = next_id.0;
+        next_id.0 += 1;
+        ew_spawned.send(CellSpawnedEvent { tick: tick.0, id, parent: e.parent, generation: e.generation, pos: e.pos, mass: e.size });
+        commands.spawn( Cell::new(id, e.parent, e.generation) )
+        .insert( Velocity::new(e.vel[0], e.vel[1]) )
+        .insert( Acceleration::default() )
+        .insert( Growth::default() )
+        .insert( Species(e.species) )
+        .insert( e.genome )
+        .insert( Body::new(e.pos[0], e.pos[1], e.size) )
+        .insert( CellRng(rng.fork()) );
+    }
+}
+
+// Gives newly spawned cells a sprite when running with a window
+fn cell_sprite_system(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    query: Query<(Entity, &Body, &Genome, Option<&Species>), Added<Cell>>,
+    mut _meshes: ResMut<Assets<Mesh>>,
+    mut _materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, body, genome, species) in query.iter() {
+        let x = body.pos[0];
+        let y = body.pos[1];
+        let d = 2. * body.radius();
+        // Cells of the same species share a color, cells not yet placed in one share their charge type's
+        let shade = genome.charge_type(genome.charge) as f32 / (genome.charge_types() as f32 - 1.).max(1.);
+        let c = match species.and_then(|s| s.0) {
+            Some(id) => species_color(id),
+            None => Color::rgb(1.0 - shade * 255. / 1024., 0.0 + shade, 1.0 - shade * 255. / 1024.)
+        };
+        commands.entity(entity)
+        // DEBUG
+        // .insert(MaterialMesh2dBundle {
+        //     mesh: meshes.add(shape::Circle::new(SCALE_FACTOR * d / 2.).into()).into(),
+        //     material: materials.add(c.into()),
+        //     transform: Transform::from_translation(Vec3::new(x, y, 0.)),
+        //     ..default()
+        // });
+        // ---
+        .insert(SpriteBundle {
+            texture: asset_server.load("particle.png"),
+            sprite: Sprite {
+                color: c,
+                custom_size: Some(Vec2::new(d, d)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(Vec3::new(x, y, 1.)),
+            ..Default::default()
+        });
+    }
+}
+
+fn motion_system(
+    mut query: Query<(&mut Body, &mut Velocity, &mut Acceleration)>,
+    bounds: Res<WorldBounds>,
+    clock: Res<SimulationClock>,
+    config: Res<SimulationConfig>
+) {
+    let mode = config.boundary;
+    let integrator = config.integrator;
+    let drag = &config.drag;
+    let dt = clock.dt();
+    query.par_for_each_mut(12, |(mut body, mut velocity, mut acceleration)| {
+        integrator.step(&mut body.pos, &mut velocity.vel, &mut acceleration, dt);
+        let radius = body.radius();
+        apply_boundary(mode, &bounds, &mut body.pos, &mut velocity.vel, radius);
+        drag.apply(&mut velocity.vel, radius, body.mass, dt);
+    });
+}
+
+// Copies simulation state onto the sprites
+fn render_sync_system(
+    mut query: Query<(&Body, &mut Transform, &mut Sprite)>
+) {
+    query.par_for_each_mut(12, |(body, mut transform, mut sprite)| {
+        sprite.custom_size = Some(Vec2::new(body.radius() * 2., body.radius() * 2.));
+        transform.translation[0] = body.pos[0];
+        transform.translation[1] = body.pos[1];
+    });
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn division_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Cell, &Body, &Velocity, &Genome, Option<&Species>, &mut CellRng)>,
+    q_partners: Query<&Genome>,
+    contacts: Res<Contacts>,
+    mut ew_spawn: EventWriter<CellSpawnEvent>,
+    mut ew_divided: EventWriter<CellDividedEvent>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>
+) {
+    let mode = config.crossover.mode;
+    for (entity, cell, body, velocity, genome, species, mut rng) in query.iter_mut() {
+        let rng = &mut rng.0;
+        if body.mass > config.division_min_size.decode(genome.division_min_size)
+            && config.division_prob.decode(genome.division_prob) > rng.gen_range(0.0..1.0) {
+            // println!("Cell with mass {} radius {} is dividing!", body.mass, body.radius());
+            // Mate with a random compatible cell from the last force pass' contacts
+            let partners: Vec<&Genome> = match mode {
+                CrossoverMode::Off => Vec::new(),
+                _ => contacts.0.get(&entity)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| q_partners.get(*e).ok())
+                    .filter(|g| genome.compatible(g, &config.crossover))
+                    .collect()
+            };
+            let partner = if partners.is_empty() { None } else { Some(partners[rng.gen_range(0..partners.len())]) };
+            let daughter_genome = |rng: &mut _| match partner {
+                Some(partner) => Genome::mutate_from(&Genome::crossover(genome, partner, mode, rng), &config, rng),
+                None => Genome::mutate_from(genome, &config, rng)
+            };
+            let div_prop = config.division_asym.decode(genome.division_asym);
+            let daughter_angle: f32 = rng.gen_range(0.0..2.*PI);
+            ew_spawn.send(
+                CellSpawnEvent {
+                    size: div_prop * body.mass,
+                    pos: body.pos + Vec2::new(daughter_angle.cos(), daughter_angle.sin()) * body.radius() / 2.,
+                    vel: velocity.vel,
+                    genome: daughter_genome(rng),
+                    parent: Some(cell.id),
+                    generation: cell.generation + 1,
+                    species: species.and_then(|s| s.0)
+                }
+            );
+            ew_spawn.send(
+                CellSpawnEvent {
+                    size: (1.0 - div_prop) * body.mass,
+                    pos: body.pos - Vec2::new(daughter_angle.cos(), daughter_angle.sin()) * body.radius() / 2.,
+                    vel: velocity.vel,
+                    genome: daughter_genome(rng),
+                    parent: Some(cell.id),
+                    generation: cell.generation + 1,
+                    species: species.and_then(|s| s.0)
+                }
+            );
+            ew_divided.send(CellDividedEvent { tick: tick.0, id: cell.id, parent: cell.parent, generation: cell.generation, pos: body.pos, mass: body.mass });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[inline(always)]
+fn cell_forces(
+    distance: f32,
+    body1: &Body, genome1: &Genome,
+    body2: &Body, genome2: &Genome,
+    config: &SimulationConfig
+    ) -> Vec2 {
+    let t2 = genome1.charge_type(genome2.charge);
+    let r_range = config.repulsion_range.decode(genome1.repulsion_range[t2]);
+    let f_range = config.force_range.decode(genome1.force_range[t2]);
+    if distance > r_range + f_range { return Vec2::new(0.0, 0.0) }
+    let r_strength = config.repulsion_strength.decode(genome1.repulsion_strength[t2]);
+    let f_strength = config.force_strength.decode(genome1.force_strength[t2]);
+    let f = force(
+        distance,
+        r_range + body1.radius(),
+        r_strength * (body2.mass / body1.mass),
+        f_range,
+        f_strength * (body2.mass / body1.mass)
+    );
+    // a = f/m
+    ((body2.pos - body1.pos).normalize() * f) / (body1.mass + EPSILON)
+}
+
+// Overlapping, close enough to eat or to mate
+#[inline(always)]
+fn in_contact(distance: f32, body1: &Body, body2: &Body) -> bool {
+    distance < body1.radius() + body2.radius()
+}
+
+fn intercell_force_system(
+    mut q_acceleration: Query<&mut Acceleration>,
+    q_cells: Query<(Entity, &Body, &Genome)>,
+    mut contacts: ResMut<Contacts>,
+    bounds: Res<WorldBounds>,
+    config: Res<SimulationConfig>
+) {
+    contacts.0.clear();
+    // Rebuild the broad phase around the current population each frame
+    let mut lower = Vec2::splat(f32::MAX);
+    let mut upper = Vec2::splat(f32::MIN);
+    let mut max_radius: f32 = 0.;
+    for (_, body, _) in q_cells.iter() {
+        lower = lower.min(body.pos);
+        upper = upper.max(body.pos);
+        max_radius = max_radius.max(body.radius());
+    }
+    if lower.x > upper.x { return }
+    let extent = upper - lower;
+    let mut qtree = CollisionQuadtree::spawn(lower.x, lower.y, extent.x, extent.y);
+    for (entity, body, _) in q_cells.iter() {
+        qtree.insert( EntityBody { entity, position: body.pos, radius: 0. } )
+    }
+    // Nothing beyond the largest force range or overlap can interact
+    let interaction_range = (config.repulsion_range.max + config.force_range.max).max(2. * max_radius);
+    let mut colliders: Vec<EntityBody> = Vec::new();
+    for (e1, body1, g1) in q_cells.iter() {
+        colliders.clear();
+        let offsets = image_offsets(body1.pos, interaction_range, config.boundary, &bounds);
+        for offset in offsets.iter() {
+            qtree.retrieve(body1.pos + *offset, interaction_range, &mut colliders);
+        }
+        if offsets.len() > 1 {
+            // Small worlds can reach the same cell through several images
+            colliders.sort_by_key(|eb| eb.entity);
+            colliders.dedup_by_key(|eb| eb.entity);
+        }
+        let mut dv = Vec2::ZERO;
+        for eb in colliders.iter() {
+            if eb.entity == e1 { continue }
+            let delta = minimum_image(eb.position - body1.pos, config.boundary, &bounds);
+            let distance = delta.length();
+            if distance > interaction_range { continue }
+            if let Ok((_, body2, g2)) = q_cells.get(eb.entity) {
+                // Cell 2 as seen from cell 1, which is only elsewhere across a toroidal edge
+                let body2 = &Body { pos: body1.pos + delta, mass: body2.mass };
+                dv += cell_forces(distance, body1, g1, body2, g2, &config);
+                if in_contact(distance, body1, body2) {
+                    contacts.0.entry(e1).or_default().push(eb.entity);
+                }
+            }
+        }
+        if let Ok(mut acceleration) = q_acceleration.get_mut(e1) {
+            acceleration.acc = dv;
+        }
+    }
+}
+
+fn cell_death_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Cell, &Body)>,
+    mut eaten: ResMut<Eaten>,
+    mut ew_died: EventWriter<CellDiedEvent>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>
+) {
+    let eaten = std::mem::take(&mut eaten.0);
+    for (entity, cell, body) in query.iter() {
+        let cause = if eaten.contains(&entity) { DeathCause::Eaten } else { DeathCause::Starved };
+        if cause == DeathCause::Eaten || body.mass < config.minimum_size {
+            ew_died.send(CellDiedEvent {
+                tick: tick.0,
+                id: cell.id,
+                parent: cell.parent,
+                generation: cell.generation,
+                cause,
+                pos: body.pos,
+                mass: body.mass
+            });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    fn random_scene(world: &mut World, n: usize, half_extent: f32, seed: u64) {
+        let mut rng = SimRng::from_seed(seed);
+        for _ in 0..n {
+            world.spawn((
+                Body::new(rng.gen_range(-half_extent..half_extent), rng.gen_range(-half_extent..half_extent), rng.gen_range(40.0..2400.0)),
+                Velocity::new(0., 0.),
+                Acceleration::default(),
+                Genome::random(&SimulationConfig::default(), &mut rng)
+            ));
+        }
+    }
+
+    // Brute force reference: every ordered pair of cells
+    fn brute_force(world: &mut World, config: &SimulationConfig, bounds: &WorldBounds) -> Vec<(Entity, Vec2, Vec<Entity>)> {
+        let cells: Vec<(Entity, Vec2, f32, Genome)> = world.query::<(Entity, &Body, &Genome)>()
+            .iter(world)
+            .map(|(e, b, g)| (e, b.pos, b.mass, g.clone()))
+            .collect();
+        cells.iter().map(|(e1, pos1, mass1, g1)| {
+            let body1 = Body::new(pos1.x, pos1.y, *mass1);
+            let mut dv = Vec2::ZERO;
+            let mut touching = Vec::new();
+            for (e2, pos2, mass2, g2) in cells.iter() {
+                if e1 == e2 { continue }
+                let delta = minimum_image(*pos2 - *pos1, config.boundary, bounds);
+                let body2 = Body { pos: *pos1 + delta, mass: *mass2 };
+                dv += cell_forces(delta.length(), &body1, g1, &body2, g2, config);
+                if in_contact(delta.length(), &body1, &body2) {
+                    touching.push(*e2);
+                }
+            }
+            (*e1, dv, touching)
+        }).collect()
+    }
+
+    fn assert_parity(config: SimulationConfig, n: usize, half_extent: f32, seed: u64) {
+        let mut world = World::new();
+        random_scene(&mut world, n, half_extent, seed);
+        let bounds = WorldBounds { width: 2. * half_extent, height: 2. * half_extent };
+        let expected = brute_force(&mut world, &config, &bounds);
+        world.insert_resource(config);
+        world.insert_resource(bounds);
+        world.insert_resource(SimulationClock::new(REFERENCE_TICKS_PER_SECOND));
+        world.init_resource::<Contacts>();
+        let mut stage = SystemStage::single_threaded().with_system(intercell_force_system);
+        stage.run(&mut world);
+        for (entity, dv, mut touching) in expected {
+            let acc = world.get::<Acceleration>(entity).unwrap().acc;
+            assert!((acc - dv).length() <= 1e-4 * dv.length().max(1.), "seed {}: {:?} != {:?}", seed, acc, dv);
+            let mut contacts = world.resource::<Contacts>().0.get(&entity).cloned().unwrap_or_default();
+            touching.sort();
+            contacts.sort();
+            assert_eq!(contacts, touching, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn quadtree_forces_match_brute_force() {
+        for seed in 0..5 {
+            assert_parity(SimulationConfig::default(), 600, 500., seed);
+        }
+    }
+
+    #[test]
+    fn quadtree_forces_match_brute_force_short_range() {
+        let config = SimulationConfig {
+            force_range: GeneRange::new(10., 60.),
+            ..default()
+        };
+        for seed in 0..5 {
+            assert_parity(config.clone(), 600, 2000., seed);
+        }
+    }
+
+    #[test]
+    fn quadtree_forces_match_brute_force_toroidal() {
+        for (force_range, half_extent) in [(GeneRange::new(10., 60.), 400.), (GeneRange::new(50., 1000.), 500.)] {
+            let config = SimulationConfig { boundary: BoundaryMode::Toroidal, force_range, ..default() };
+            for seed in 0..3 {
+                assert_parity(config.clone(), 400, half_extent, seed);
+            }
+        }
+    }
+
+    // Every cell's state in query order, as raw bytes
+    fn world_state(world: &mut World) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (entity, body, velocity, growth, genome, species) in world.query::<(Entity, &Body, &Velocity, &Growth, &Genome, &Species)>().iter(world) {
+            bytes.extend(entity.to_bits().to_le_bytes());
+            bytes.extend(species.0.map_or(-1, |id| id as i64).to_le_bytes());
+            for v in [body.pos.x, body.pos.y, body.mass, velocity.vel.x, velocity.vel.y, growth.0] {
+                bytes.extend(v.to_le_bytes());
+            }
+            for gene in Gene::ALL {
+                bytes.extend(genome.gene(gene));
+            }
+        }
+        bytes
+    }
+
+    // A headless app as the binary builds one, a tick per update
+    fn simulation_app(options: &SimulationOptions, config: SimulationConfig) -> App {
+        let frame_dt = SimulationClock::new(config.ticks_per_second).step;
+        let mut app = App::new();
+        app.add_plugin(HeadlessPlugin { frame_dt, max_ticks: None, max_wall_time: None })
+            .add_plugin(GeneticParticlesPlugin { config, snapshot: None, options: options.clone() });
+        app
+    }
+
+    fn run_headless(seed: u64, ticks: u64) -> Vec<u8> {
+        let options = SimulationOptions { seed: Some(seed), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        for _ in 0..ticks {
+            app.update();
+        }
+        world_state(&mut app.world)
+    }
+
+    #[test]
+    fn same_seed_reproduces_world_state() {
+        let a = run_headless(42, 300);
+        let b = run_headless(42, 300);
+        assert!(!a.is_empty());
+        assert!(a == b, "runs with the same seed diverged");
+        assert!(a != run_headless(43, 300), "runs with different seeds matched");
+    }
+
+    // Updates a headless app until it has run `ticks` ticks
+    fn run_ticks(app: &mut App, ticks: u64) -> Vec<u8> {
+        while app.world.resource::<SimulationTick>().0 < ticks {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<SimulationTick>().0, ticks);
+        world_state(&mut app.world)
+    }
+
+    #[test]
+    fn ticks_do_not_depend_on_frame_rate() {
+        let options = SimulationOptions { seed: Some(42), ..default() };
+        let config = SimulationConfig { species: SpeciesConfig { interval: 0.5, ..default() }, ..default() };
+        let mut app = simulation_app(&options, config.clone());
+        let step = app.world.resource::<SimulationClock>().step;
+        let reference = run_ticks(&mut app, 120);
+
+        // Three ticks per update
+        let mut app = simulation_app(&options, config.clone());
+        app.world.resource_mut::<HeadlessRun>().frame_dt = step * 3;
+        assert!(run_ticks(&mut app, 120) == reference, "fewer, longer frames changed the outcome");
+
+        // Four ticks per update through the time scale
+        let mut app = simulation_app(&options, config);
+        app.world.resource_mut::<SimulationClock>().time_scale = 4.;
+        assert!(run_ticks(&mut app, 120) == reference, "a faster time scale changed the outcome");
+    }
+
+    #[test]
+    fn headless_run_exits_at_its_tick_budget() {
+        let config = SimulationConfig::default();
+        let frame_dt = SimulationClock::new(config.ticks_per_second).step;
+        let options = SimulationOptions { seed: Some(42), ..default() };
+        let mut app = App::new();
+        app.add_plugin(HeadlessPlugin { frame_dt, max_ticks: Some(50), max_wall_time: None })
+            .add_plugin(GeneticParticlesPlugin { config, snapshot: None, options });
+        while app.world.resource::<Events<bevy::app::AppExit>>().is_empty() {
+            app.update();
+            assert!(app.world.resource::<SimulationTick>().0 <= 50, "the run went past its budget");
+        }
+        assert_eq!(app.world.resource::<SimulationTick>().0, 50);
+    }
+
+    #[test]
+    fn paused_clock_runs_no_ticks() {
+        let options = SimulationOptions { seed: Some(42), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        let before = run_ticks(&mut app, 30);
+        app.world.resource_mut::<SimulationClock>().paused = true;
+        for _ in 0..30 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<SimulationTick>().0, 30);
+        assert!(world_state(&mut app.world) == before, "the world changed while paused");
+    }
+
+    #[test]
+    fn every_charge_pair_is_safe() {
+        for charge_types in [1, 4, 7, CHARGE_COUNT] {
+            let config = SimulationConfig { charge_types, ..default() };
+            let mut rng = SimRng::from_seed(7);
+            let mut g1 = Genome::random(&config, &mut rng);
+            let mut g2 = Genome::random(&config, &mut rng);
+            let body1 = Body::new(0., 0., 400.);
+            let body2 = Body::new(15., 5., 300.);
+            let distance = body1.pos.distance(body2.pos);
+            for c1 in 0..=u8::MAX {
+                for c2 in 0..=u8::MAX {
+                    g1.charge = c1;
+                    g2.charge = c2;
+                    let dv = cell_forces(distance, &body1, &g1, &body2, &g2, &config);
+                    let eat = g1.eat_rate_on(&g2, &config) - g2.eat_rate_on(&g1, &config);
+                    assert!(dv.is_finite() && eat.is_finite(), "charges {} and {}", c1, c2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn charges_bin_evenly_into_types() {
+        let config = SimulationConfig { charge_types: 4, ..default() };
+        let g = Genome::random(&config, &mut SimRng::from_seed(5));
+        let mut counts = [0; 4];
+        for c in 0..=u8::MAX {
+            counts[g.charge_type(c)] += 1;
+        }
+        assert_eq!(counts, [64; 4]);
+        assert_eq!(g.charge_type(0), 0);
+        assert_eq!(g.charge_type(u8::MAX), 3);
+    }
+
+    #[test]
+    fn random_and_mutated_genomes_reach_every_charge() {
+        let config = SimulationConfig { evolution_probability: 1., ..default() };
+        let mut rng = SimRng::from_seed(11);
+        let mut seen = [false; CHARGE_COUNT];
+        let mut g = Genome::random(&config, &mut rng);
+        for _ in 0..20000 {
+            seen[Genome::random(&config, &mut rng).charge as usize] = true;
+            g = Genome::mutate_from(&g, &config, &mut rng);
+            seen[g.charge as usize] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+    }
+
+    #[test]
+    fn forced_mutation_changes_every_gene() {
+        let config = SimulationConfig { evolution_probability: 1., charge_types: 8, ..default() };
+        let mut rng = SimRng::from_seed(13);
+        let parent = Genome::random(&config, &mut rng);
+        let mut changed = [false; Gene::ALL.len()];
+        for _ in 0..4 {
+            let child = Genome::mutate_from(&parent, &config, &mut rng);
+            // Destructured without .. so a new field fails to compile until it's covered here
+            let Genome {
+                charge,
+                division_prob,
+                division_asym,
+                division_min_size,
+                repulsion_range,
+                repulsion_strength,
+                force_range,
+                force_strength,
+                eat_rate,
+                uptake,
+                compatibility
+            } = &child;
+            let fields = [
+                charge != &parent.charge,
+                division_prob != &parent.division_prob,
+                division_asym != &parent.division_asym,
+                division_min_size != &parent.division_min_size,
+                repulsion_range != &parent.repulsion_range,
+                repulsion_strength != &parent.repulsion_strength,
+                force_range != &parent.force_range,
+                force_strength != &parent.force_strength,
+                eat_rate != &parent.eat_rate,
+                uptake != &parent.uptake,
+                compatibility != &parent.compatibility
+            ];
+            for (c, f) in changed.iter_mut().zip(fields) {
+                *c |= f;
+            }
+        }
+        assert!(changed.iter().all(|c| *c), "genes left unmutated: {:?}", changed);
+        for gene in Gene::ALL {
+            assert_eq!(parent.gene(gene).len(), if gene.is_table() { 8 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn dividing_cells_mate_with_touching_partners() {
+        let mut config = SimulationConfig { evolution_probability: 0., division_prob: GeneRange::new(1., 1.), ..default() };
+        config.crossover.mode = CrossoverMode::Uniform;
+        let mut world = World::new();
+        let mut rng = SimRng::from_seed(29);
+        let mut a = Genome::random(&config, &mut rng);
+        a.division_min_size = 0;
+        let mut b = a.clone();
+        for gene in Gene::ALL.into_iter().filter(|g| g.is_table()) {
+            b.gene_mut(gene).iter_mut().for_each(|v| *v = v.wrapping_add(1));
+        }
+        world.spawn((Cell::new(0, None, 0), Body::new(0., 0., 1000.), Velocity::new(0., 0.), a.clone(), CellRng(rng.fork())));
+        world.spawn((Cell::new(1, None, 0), Body::new(5., 0., 1000.), Velocity::new(0., 0.), b.clone(), CellRng(rng.fork())));
+        world.insert_resource(config);
+        world.insert_resource(WorldBounds { width: 1000., height: 1000. });
+        world.insert_resource(SimulationClock::new(REFERENCE_TICKS_PER_SECOND));
+        world.init_resource::<SimulationTick>();
+        world.init_resource::<Contacts>();
+        world.init_resource::<Events<CellSpawnEvent>>();
+        world.init_resource::<Events<CellDividedEvent>>();
+        let mut stage = SystemStage::single_threaded()
+            .with_system(intercell_force_system)
+            .with_system(division_system.after(intercell_force_system));
+        stage.run(&mut world);
+        let events = world.resource::<Events<CellSpawnEvent>>();
+        let daughters: Vec<&Genome> = events.iter_current_update_events().map(|e| &e.genome).collect();
+        assert_eq!(daughters.len(), 4);
+        // With uniform crossover over hundreds of table entries each daughter gets some of both parents
+        for d in daughters {
+            assert!(d.force_range.iter().zip(&a.force_range).any(|(x, y)| x == y));
+            assert!(d.force_range.iter().zip(&b.force_range).any(|(x, y)| x == y));
+        }
+    }
+
+    // A predator overlapping its prey, and a bystander near the predator but not touching it
+    fn predation_world(metabolism: MetabolismConfig) -> (World, SystemStage, [Entity; 3]) {
+        let config = SimulationConfig { charge_types: 1, metabolism, ..default() };
+        let mut predator = Genome::zeroed(1);
+        predator.eat_rate[0] = config.eat_rate.encode(2.);
+        let mut prey = Genome::zeroed(1);
+        prey.eat_rate[0] = config.eat_rate.encode(-2.);
+        let mut world = World::new();
+        let a = world.spawn((Cell::new(0, None, 0), Body::new(0., 0., 500.), Velocity::new(0., 0.), Growth::default(), predator)).id();
+        let b = world.spawn((Cell::new(1, None, 0), Body::new(15., 0., 100.), Velocity::new(0., 0.), Growth::default(), prey)).id();
+        let c = world.spawn((Cell::new(2, None, 0), Body::new(-30., 0., 100.), Velocity::new(0., 0.), Growth::default(), Genome::zeroed(1))).id();
+        world.insert_resource(config);
+        world.insert_resource(WorldBounds { width: 1000., height: 1000. });
+        world.insert_resource(SimulationClock::new(REFERENCE_TICKS_PER_SECOND));
+        world.init_resource::<Contacts>();
+        world.init_resource::<Eaten>();
+        world.init_resource::<BiomassLedger>();
+        world.init_resource::<Events<CellDiedEvent>>();
+        world.init_resource::<SimulationTick>();
+        let stage = SystemStage::single_threaded()
+            .with_system(intercell_force_system)
+            .with_system(predation_system.after(intercell_force_system))
+            .with_system(metabolism_system.after(predation_system))
+            .with_system(cell_death_system.after(metabolism_system));
+        (world, stage, [a, b, c])
+    }
+
+    // Runs the stage until the prey is gone and returns its death
+    fn eat_prey(world: &mut World, stage: &mut SystemStage, prey: Entity) -> (u32, CellDiedEvent) {
+        let mut ticks = 0;
+        while world.get_entity(prey).is_some() {
+            stage.run(world);
+            ticks += 1;
+            assert!(ticks < 100, "the prey was never eaten");
+        }
+        let died = world.resource::<Events<CellDiedEvent>>();
+        let deaths: Vec<&CellDiedEvent> = died.iter_current_update_events().collect();
+        assert_eq!(deaths.len(), 1);
+        (ticks, deaths[0].clone())
+    }
+
+    #[test]
+    fn predators_eat_overlapping_prey_whole() {
+        let metabolism = MetabolismConfig { uptake: 0., maintenance: 0., movement_cost: 0., predation_efficiency: 0.75 };
+        let (mut world, mut stage, [a, b, c]) = predation_world(metabolism);
+        stage.run(&mut world);
+        assert_eq!(world.resource::<Contacts>().0.get(&a), Some(&vec![b]));
+        let predator_mass = world.get::<Body>(a).unwrap().mass;
+        let prey_mass = world.get::<Body>(b).unwrap().mass;
+        assert!((prey_mass - 96.).abs() < 1e-3, "prey at {}", prey_mass);
+        assert!((predator_mass - 503.).abs() < 1e-3, "predator at {}", predator_mass);
+        assert_eq!(world.get::<Body>(c).unwrap().mass, 100.);
+
+        // Bitten down to the minimum size and then swallowed whole
+        let (ticks, death) = eat_prey(&mut world, &mut stage, b);
+        assert_eq!(ticks + 1, 16);
+        assert_eq!((death.id, death.cause, death.mass), (1, DeathCause::Eaten, 0.));
+        let ledger = world.resource::<BiomassLedger>();
+        let predator_mass = world.get::<Body>(a).unwrap().mass as f64;
+        assert!((predator_mass - 500. - 75.).abs() < 1e-2, "predator at {}", predator_mass);
+        assert!((ledger.predation_loss - 25.).abs() < 1e-2, "{}", ledger.predation_loss);
+
+        // The swallowed prey pays no costs on top of the last bite
+        let (mut world, mut stage, [_, b, _]) = predation_world(MetabolismConfig { predation_efficiency: 0.75, ..default() });
+        let (_, death) = eat_prey(&mut world, &mut stage, b);
+        assert_eq!((death.cause, death.mass), (DeathCause::Eaten, 0.));
+    }
+
+    #[test]
+    fn growth_from_eating_survives_the_rest_of_the_tick() {
+        let options = SimulationOptions { seed: Some(7), ..default() };
+        let metabolism = MetabolismConfig { uptake: 0., maintenance: 0., movement_cost: 0., predation_efficiency: 0.75 };
+        let config = SimulationConfig { charge_types: 1, metabolism, division_prob: GeneRange::new(0., 0.), ..default() };
+        let mut predator = Genome::zeroed(1);
+        predator.eat_rate[0] = config.eat_rate.encode(2.);
+        let mut prey = Genome::zeroed(1);
+        prey.eat_rate[0] = config.eat_rate.encode(-2.);
+        let mut app = simulation_app(&options, config);
+        run_ticks(&mut app, 1);
+        // Swap the random population for a predator overlapping its prey
+        let cells: Vec<Entity> = app.world.query_filtered::<Entity, With<Cell>>().iter(&app.world).collect();
+        for entity in cells {
+            app.world.despawn(entity);
+        }
+        let mut rng = SimRng::from_seed(7);
+        let a = app.world.spawn((
+            Cell::new(100, None, 0), Body::new(0., 0., 500.), Velocity::new(0., 0.), Acceleration::default(),
+            Growth::default(), predator, CellRng(rng.fork())
+        )).id();
+        let b = app.world.spawn((
+            Cell::new(101, None, 0), Body::new(15., 0., 100.), Velocity::new(0., 0.), Acceleration::default(),
+            Growth::default(), prey, CellRng(rng.fork())
+        )).id();
+        // The whole schedule, motion and all, runs between the bite and metabolism applying it
+        run_ticks(&mut app, 2);
+        let predator_mass = app.world.get::<Body>(a).unwrap().mass;
+        let prey_mass = app.world.get::<Body>(b).unwrap().mass;
+        assert!((predator_mass - 503.).abs() < 1e-3, "predator at {}", predator_mass);
+        assert!((prey_mass - 96.).abs() < 1e-3, "prey at {}", prey_mass);
+        assert_eq!((app.world.get::<Growth>(a).unwrap().0, app.world.get::<Growth>(b).unwrap().0), (0., 0.));
+    }
+
+    // Pairs of systems in a stage that touch the same data with neither ordered before the
+    // other, as Bevy's own ambiguity report would list them
+    fn ambiguities(stage: &SystemStage, world: &World) -> Vec<String> {
+        let systems = stage.parallel_systems();
+        // Systems are sorted, so everything a system depends on comes before it
+        let mut before: Vec<Vec<bool>> = Vec::new();
+        for system in systems {
+            let mut deps = vec![false; systems.len()];
+            for &d in system.dependencies() {
+                deps[d] = true;
+                for (i, dep) in before[d].iter().enumerate() {
+                    deps[i] |= dep;
+                }
+            }
+            before.push(deps);
+        }
+        let mut found = Vec::new();
+        for (a, system_a) in systems.iter().enumerate() {
+            for (b, system_b) in systems.iter().enumerate().skip(a + 1) {
+                if before[b][a] || system_a.component_access().is_compatible(system_b.component_access()) { continue }
+                let conflicts: Vec<String> = system_a.component_access().get_conflicts(system_b.component_access()).into_iter()
+                    .map(|id| world.components().get_info(id).map_or("?".to_string(), |info| info.name().to_string()))
+                    .collect();
+                found.push(format!("{} and {} over {:?}", system_a.name(), system_b.name(), conflicts));
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn physics_schedule_has_no_ambiguities() {
+        for draw in [false, true] {
+            let options = SimulationOptions { seed: Some(3), draw, ..default() };
+            let mut app = simulation_app(&options, SimulationConfig::default());
+            if draw {
+                // What the windowed systems need from DefaultPlugins, without opening a window
+                app.add_plugin(AssetPlugin::default())
+                    .add_asset::<Image>()
+                    .add_asset::<Mesh>()
+                    .add_asset::<ColorMaterial>()
+                    .init_resource::<Windows>()
+                    .init_resource::<Input<KeyCode>>();
+            }
+            run_ticks(&mut app, 1);
+            for label in [CoreStage::Update, CoreStage::PostUpdate] {
+                let stage = app.schedule.get_stage::<SystemStage>(label).unwrap();
+                let found = ambiguities(stage, &app.world);
+                assert!(found.is_empty(), "unordered systems with draw {}:\n{}", draw, found.join("\n"));
+            }
+        }
+    }
+
+    // Ids and ancestry of every live cell, sorted
+    fn cell_ids(world: &mut World) -> Vec<(u32, Option<u32>, u32)> {
+        let mut ids: Vec<_> = world.query::<&Cell>().iter(world).map(|c| (c.id, c.parent, c.generation)).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn snapshots_keep_daughters_waiting_to_spawn() {
+        let options = SimulationOptions { seed: Some(45), ..default() };
+        let config = SimulationConfig::default();
+        let mut app = simulation_app(&options, config.clone());
+        // Division is checked on tick 6 at 60 ticks per second, its daughters spawn on tick 7
+        run_ticks(&mut app, 6);
+        let snapshot = SystemState::<SnapshotSource>::new(&mut app.world).get(&app.world).capture();
+        assert!(!snapshot.pending.is_empty(), "no cell divided");
+        let snapshot: Snapshot = serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+        run_ticks(&mut app, 7);
+
+        let mut resumed = App::new();
+        resumed.add_plugin(HeadlessPlugin { frame_dt: SimulationClock::new(config.ticks_per_second).step, max_ticks: None, max_wall_time: None })
+            .add_plugin(GeneticParticlesPlugin { config, snapshot: Some(snapshot), options: SimulationOptions::default() });
+        run_ticks(&mut resumed, 7);
+        assert_eq!(cell_ids(&mut resumed.world), cell_ids(&mut app.world));
+    }
+
+    #[test]
+    fn lineage_links_every_cell_to_its_parent() {
+        let options = SimulationOptions { seed: Some(31), lineage: Some("unused.csv".into()), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        for _ in 0..600 {
+            app.update();
+        }
+        let mut ids: Vec<u32> = app.world.query::<&Cell>().iter(&app.world).map(|c| c.id).collect();
+        let log = app.world.resource::<LineageLog>();
+        assert!(log.cells.len() > N_PARTICLES as usize, "no cell divided");
+        for r in log.cells.values() {
+            match r.parent {
+                Some(parent) => {
+                    let p = &log.cells[&parent];
+                    assert_eq!(r.generation, p.generation + 1);
+                    // Daughters are spawned from events on the update after the division
+                    assert!(p.death_tick.is_some_and(|t| t <= r.birth_tick));
+                }
+                None => assert_eq!(r.generation, 0)
+            }
+        }
+        let alive = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), alive);
+        assert!(ids.iter().all(|id| log.cells[id].death_tick.is_none()));
+    }
+
+    #[test]
+    fn daughters_start_in_their_parents_species() {
+        let options = SimulationOptions { seed: Some(46), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        let mut species: std::collections::HashMap<u32, Option<u32>> = std::collections::HashMap::new();
+        let mut inherited = 0;
+        for _ in 0..300 {
+            app.update();
+            let cells: Vec<(u32, Option<u32>, Option<u32>)> = app.world.query::<(&Cell, &Species)>()
+                .iter(&app.world)
+                .map(|(cell, s)| (cell.id, cell.parent, s.0))
+                .collect();
+            for (id, parent, s) in cells.iter() {
+                // Daughters of a clustered cell get colored by species right away
+                if let Some(Some(_)) = parent.filter(|_| !species.contains_key(id)).and_then(|p| species.get(&p)) {
+                    assert!(s.is_some(), "cell {} spawned without its parent's species", id);
+                    inherited += 1;
+                }
+            }
+            species.extend(cells.iter().map(|(id, _, s)| (*id, *s)));
+        }
+        assert!(inherited > 0);
+    }
+
+    #[test]
+    fn stats_rows_account_for_every_birth_and_death() {
+        let path = std::env::temp_dir().join(format!("stats-{}.csv", std::process::id()));
+        let options = SimulationOptions { seed: Some(41), stats: Some(path.clone()), ..default() };
+        let config = SimulationConfig { stats: StatsConfig { interval: 0.5 }, ..default() };
+        let mut app = simulation_app(&options, config);
+        run_ticks(&mut app, 900);
+        // Paused updates write nothing
+        app.world.resource_mut::<SimulationClock>().paused = true;
+        for _ in 0..100 {
+            app.update();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header[..5], ["tick", "time", "population", "births", "deaths"]);
+        assert!(header.contains(&"division_prob_variance") && !header.contains(&"force_range_mean"));
+        let rows: Vec<Vec<f64>> = lines.map(|l| l.split(',').map(|v| v.parse().unwrap()).collect()).collect();
+        // A row every 30 ticks up to the last one run, timed in simulated seconds
+        assert_eq!(rows.iter().map(|row| row[0]).collect::<Vec<_>>(), (1..30).map(|i| i as f64 * 30.).collect::<Vec<_>>());
+        let mut population = 0.;
+        let mut divided = false;
+        for row in rows {
+            assert_eq!(row.len(), header.len());
+            assert_eq!(row[1], row[0] / 60.);
+            population += row[3] - row[4];
+            divided |= row[4] > 0.;
+            assert_eq!(row[2], population);
+        }
+        assert!(divided);
+    }
+
+    #[test]
+    fn biomass_ledger_accounts_for_all_mass() {
+        let options = SimulationOptions { seed: Some(44), ..default() };
+        let uptake = MetabolismConfig { uptake: 0.05, ..default() };
+        // Foraging takes over from flat uptake once nutrients are enabled
+        for nutrients_enabled in [false, true] {
+            let nutrients = NutrientConfig { enabled: nutrients_enabled, ..default() };
+            let config = SimulationConfig { metabolism: uptake.clone(), nutrients, ..default() };
+            let mut app = simulation_app(&options, config);
+            for _ in 0..600 {
+                app.update();
+                let actual = total_biomass(&mut app.world);
+                let ledger = app.world.resource::<BiomassLedger>();
+                assert!((ledger.expected() - actual).abs() <= 1e-3 * actual.max(1.), "{}", ledger.report(actual));
+            }
+            let ledger = app.world.resource::<BiomassLedger>();
+            assert!(ledger.divided > 0. && ledger.maintenance > 0. && ledger.movement > 0.);
+            assert_eq!((ledger.uptake > 0., ledger.foraged > 0.), (!nutrients_enabled, nutrients_enabled));
+        }
+
+        // With neither uptake nor nutrients the cells can only burn mass
+        let nutrients = NutrientConfig { enabled: false, ..default() };
+        let mut app = simulation_app(&options, SimulationConfig { nutrients, ..default() });
+        // The initial population spawns on the first tick, the first update has none
+        app.update();
+        app.update();
+        let initial = total_biomass(&mut app.world);
+        assert!(initial > 0.);
+        for _ in 0..300 {
+            app.update();
+        }
+        let total = total_biomass(&mut app.world);
+        assert!(total < initial, "biomass grew from {} to {}", initial, total);
+    }
+
+    #[test]
+    fn default_config_sustains_a_population() {
+        let options = SimulationOptions { seed: Some(42), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        // The initial population spawns on the first tick
+        run_ticks(&mut app, 2);
+        let initial = total_biomass(&mut app.world);
+        run_ticks(&mut app, 1000);
+        // Without a mass source the cells could only burn what they started with
+        let total = total_biomass(&mut app.world);
+        assert!(total > initial, "biomass fell from {} to {}", initial, total);
+        assert!(app.world.query::<&Cell>().iter(&app.world).len() > 0);
+    }
+
+    #[test]
+    fn event_log_accounts_for_every_cell() {
+        let path = std::env::temp_dir().join(format!("events-{}.jsonl", std::process::id()));
+        let options = SimulationOptions { seed: Some(43), event_log: Some(path.clone()), event_level: EventLevel::Debug, ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        for _ in 0..900 {
+            app.update();
+        }
+        let population = app.world.query::<&Cell>().iter(&app.world).len() as i64;
+        drop(app);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut counts = std::collections::HashMap::new();
+        for line in text.lines() {
+            let v: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(v["tick"].is_u64() && v["id"].is_u64() && v["pos"].is_array() && v["mass"].is_number());
+            let event = v["event"].as_str().unwrap().to_string();
+            if event == "died" {
+                assert_eq!(v["cause"], "starved");
+            }
+            *counts.entry(event).or_insert(0i64) += 1;
+        }
+        assert!(counts["divided"] > 0);
+        let removed = counts["divided"] + counts.get("died").unwrap_or(&0);
+        assert_eq!(counts["spawned"] - removed, population);
+    }
+
+    // Id, parent, birth tick and death tick of every cell in the lineage
+    type LineageTicks = Vec<(u32, Option<u32>, u64, Option<u64>)>;
+
+    // Runs seed 43 with the event log and lineage on, `ticks_per_frame` ticks per update
+    fn logged_run(ticks_per_frame: u32, name: &str) -> (String, LineageTicks) {
+        let path = std::env::temp_dir().join(format!("events-{}-{}.jsonl", name, std::process::id()));
+        let options = SimulationOptions { seed: Some(43), event_log: Some(path.clone()), event_level: EventLevel::Debug, lineage: Some("unused.csv".into()), ..default() };
+        let mut app = simulation_app(&options, SimulationConfig::default());
+        let step = app.world.resource::<SimulationClock>().step;
+        app.world.resource_mut::<HeadlessRun>().frame_dt = step * ticks_per_frame;
+        run_ticks(&mut app, 800);
+        let mut cells: Vec<_> = app.world.resource::<LineageLog>().cells.iter()
+            .map(|(id, r)| (*id, r.parent, r.birth_tick, r.death_tick))
+            .collect();
+        cells.sort();
+        drop(app);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        (text, cells)
+    }
+
+    #[test]
+    fn event_log_and_lineage_do_not_depend_on_frame_rate() {
+        let (text, cells) = logged_run(1, "single");
+        let (batched_text, batched_cells) = logged_run(4, "batched");
+        assert!(cells.iter().any(|c| c.3.is_some()), "no cell divided or died");
+        assert!(cells == batched_cells, "lineage ticks changed with the frame rate");
+        assert!(text == batched_text, "the event log changed with the frame rate");
+        let ticks: Vec<u64> = text.lines().map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["tick"].as_u64().unwrap()).collect();
+        assert!(ticks.windows(2).all(|w| w[0] <= w[1]), "events are out of time order");
+    }
+
+}
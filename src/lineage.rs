@@ -0,0 +1,163 @@
+use std::{collections::BTreeMap, fmt::Write, fs, path::{Path, PathBuf}};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{CellDiedEvent, CellDividedEvent, CellSpawnedEvent};
+
+// Id the next spawned cell gets
+#[derive(Resource, Default)]
+pub struct NextCellId(pub u32);
+
+pub struct LineageRecord {
+    pub parent: Option<u32>,
+    pub generation: u32,
+    pub birth_tick: u64,
+    pub death_tick: Option<u64>  // Dividing counts as dying, the daughters carry on
+}
+
+// Every cell born while the log existed, by id. Only kept when --lineage is given.
+#[derive(Resource, Default)]
+pub struct LineageLog {
+    pub cells: BTreeMap<u32, LineageRecord>
+}
+
+// Where to write the lineage when the app exits. A .nwk or .newick path gets Newick, anything else CSV.
+#[derive(Resource)]
+pub struct LineageOnExit(pub PathBuf);
+
+impl LineageLog {
+
+    pub fn born(&mut self, id: u32, parent: Option<u32>, generation: u32, tick: u64) {
+        self.cells.insert(id, LineageRecord { parent, generation, birth_tick: tick, death_tick: None });
+    }
+
+    pub fn died(&mut self, id: u32, tick: u64) {
+        if let Some(record) = self.cells.get_mut(&id) {
+            record.death_tick = Some(tick);
+        }
+    }
+
+    // One parent,child edge per line. Cells whose parent isn't known have an empty parent.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("parent,child,generation,birth_tick,death_tick\n");
+        for (id, r) in self.cells.iter() {
+            let parent = r.parent.map_or(String::new(), |p| p.to_string());
+            let death = r.death_tick.map_or(String::new(), |t| t.to_string());
+            writeln!(out, "{},{},{},{},{}", parent, id, r.generation, r.birth_tick, death).unwrap();
+        }
+        out
+    }
+
+    // Nodes are labeled with cell ids and branch lengths are ticks between the births of parent
+    // and child. Founders without a known parent hang off an unlabeled root.
+    pub fn to_newick(&self) -> String {
+        enum Step { Open(u32), Close(u32), Comma }
+
+        let mut children: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        let mut roots = Vec::new();
+        for (id, r) in self.cells.iter() {
+            match r.parent.filter(|p| self.cells.contains_key(p)) {
+                Some(parent) => children.entry(parent).or_default().push(*id),
+                None => roots.push(*id)
+            }
+        }
+        let label = |out: &mut String, id: u32| {
+            let r = &self.cells[&id];
+            let parent_birth = r.parent.and_then(|p| self.cells.get(&p)).map_or(r.birth_tick, |p| p.birth_tick);
+            write!(out, "{}:{}", id, r.birth_tick - parent_birth).unwrap();
+        };
+        // Iterative so deep lineages can't overflow the stack
+        let mut out = String::from("(");
+        let mut stack: Vec<Step> = Vec::new();
+        for (i, root) in roots.iter().enumerate().rev() {
+            stack.push(Step::Open(*root));
+            if i > 0 { stack.push(Step::Comma) }
+        }
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Open(id) => match children.get(&id) {
+                    Some(kids) => {
+                        out.push('(');
+                        stack.push(Step::Close(id));
+                        for (i, kid) in kids.iter().enumerate().rev() {
+                            stack.push(Step::Open(*kid));
+                            if i > 0 { stack.push(Step::Comma) }
+                        }
+                    }
+                    None => label(&mut out, id)
+                },
+                Step::Close(id) => {
+                    out.push(')');
+                    label(&mut out, id);
+                }
+                Step::Comma => out.push(',')
+            }
+        }
+        out.push_str(");\n");
+        out
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("nwk" | "newick") => self.to_newick(),
+            _ => self.to_csv()
+        };
+        fs::write(path, text).map_err(|e| format!("Failed to write lineage {}: {}", path.display(), e))
+    }
+
+}
+
+// Records births and deaths at the ticks they happened on
+pub fn lineage_system(
+    mut log: ResMut<LineageLog>,
+    mut er_spawned: EventReader<CellSpawnedEvent>,
+    mut er_divided: EventReader<CellDividedEvent>,
+    mut er_died: EventReader<CellDiedEvent>
+) {
+    for e in er_spawned.iter() {
+        log.born(e.id, e.parent, e.generation, e.tick);
+    }
+    for (id, tick) in er_divided.iter().map(|e| (e.id, e.tick)).chain(er_died.iter().map(|e| (e.id, e.tick))) {
+        log.died(id, tick);
+    }
+}
+
+pub fn lineage_on_exit_system(
+    mut er_exit: EventReader<AppExit>,
+    path: Res<LineageOnExit>,
+    log: Res<LineageLog>
+) {
+    if er_exit.iter().next().is_some() {
+        match log.save(&path.0) {
+            Ok(()) => info!("Saved lineage of {} cells to {}", log.cells.len(), path.0.display()),
+            Err(e) => error!("{}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lineage_exports_as_newick_and_csv() {
+        let mut log = LineageLog::default();
+        log.born(0, None, 0, 0);
+        log.born(1, None, 0, 0);
+        log.born(2, Some(0), 1, 10);
+        log.born(3, Some(0), 1, 10);
+        log.died(0, 10);
+        log.born(4, Some(3), 2, 25);
+        log.born(5, Some(3), 2, 25);
+        log.died(3, 25);
+        log.died(5, 30);
+        assert_eq!(log.to_newick(), "((2:10,(4:15,5:15)3:10)0:0,1:0);\n");
+        assert_eq!(log.to_csv(), "parent,child,generation,birth_tick,death_tick\n\
+            ,0,0,0,10\n\
+            ,1,0,0,\n\
+            0,2,1,10,\n\
+            0,3,1,10,25\n\
+            3,4,2,25,\n\
+            3,5,2,25,30\n");
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{Body, CellDiedEvent, CellDividedEvent, CellSpawnedEvent, Eaten, SimulationClock, SimulationConfig, Velocity};
+
+// Mass moved to or from a cell during a tick, such as by predation, for metabolism to apply
+#[derive(Component, Clone, Copy, Default)]
+pub struct Growth(pub f32);
+
+// Mass is a cell's energy store. All rates are per reference tick.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetabolismConfig {
+    pub uptake: f32,  // Mass every cell takes up from nowhere, off unless set. Foraging replaces it when nutrients are enabled.
+    pub maintenance: f32,  // Fraction of its mass a cell burns just staying alive
+    pub movement_cost: f32,  // Mass burned per unit of mass and squared speed
+    pub predation_efficiency: f32  // Share of the mass bitten off prey that the predator keeps
+}
+
+impl Default for MetabolismConfig {
+    fn default() -> Self {
+        Self {
+            uptake: 0.,
+            maintenance: 0.0001,
+            movement_cost: 0.005,
+            predation_efficiency: 0.8
+        }
+    }
+}
+
+// Running totals of every way biomass enters or leaves the world, in f64 so they don't
+// lose track of small flows over long runs
+#[derive(Resource, Default, Debug)]
+pub struct BiomassLedger {
+    pub spawned: f64,  // Every cell that appeared, the initial population and daughters alike
+    pub divided: f64,  // Cells that split into daughters
+    pub died: f64,
+    pub uptake: f64,
+    pub foraged: f64,  // Taken from the nutrient field
+    pub maintenance: f64,
+    pub movement: f64,
+    pub predation_loss: f64  // Bitten off prey but not kept by the predator
+}
+
+impl BiomassLedger {
+
+    // What the cells should weigh together if nothing went unaccounted
+    pub fn expected(&self) -> f64 {
+        self.spawned - self.divided - self.died + self.uptake + self.foraged - self.maintenance - self.movement - self.predation_loss
+    }
+
+    pub fn report(&self, actual: f64) -> String {
+        format!(
+            "Biomass: {:.1} spawned - {:.1} divided - {:.1} died + {:.1} uptake + {:.1} foraged - {:.1} maintenance - {:.1} movement - {:.1} predation loss = {:.1}, found {:.1} ({:+.1})",
+            self.spawned,
+            self.divided,
+            self.died,
+            self.uptake,
+            self.foraged,
+            self.maintenance,
+            self.movement,
+            self.predation_loss,
+            self.expected(),
+            actual,
+            actual - self.expected()
+        )
+    }
+
+}
+
+// Mass of the live cells. Daughters waiting to be spawned count once they are, their
+// parent's mass has already left the ledger as divided.
+pub fn total_biomass(world: &mut World) -> f64 {
+    world.query::<&Body>().iter(world).map(|body| body.mass as f64).sum()
+}
+
+// Applies uptake, eating and the costs of living, after motion so the movement cost
+// sees the speed the cell ends the tick with
+pub fn metabolism_system(
+    mut query: Query<(Entity, &mut Body, &Velocity, &mut Growth)>,
+    eaten: Res<Eaten>,
+    mut ledger: ResMut<BiomassLedger>,
+    clock: Res<SimulationClock>,
+    config: Res<SimulationConfig>
+) {
+    let dt = clock.dt();
+    let metabolism = &config.metabolism;
+    let uptake = if config.nutrients.enabled { 0. } else { metabolism.uptake * dt };
+    for (entity, mut body, velocity, mut growth) in query.iter_mut() {
+        // Eaten cells only lose what was bitten off, the death system removes them this tick
+        if eaten.0.contains(&entity) {
+            body.mass += growth.0;
+            growth.0 = 0.;
+            continue
+        }
+        let maintenance = metabolism.maintenance * body.mass * dt;
+        let movement = metabolism.movement_cost * body.mass * velocity.vel.length_squared() * dt;
+        body.mass += uptake + growth.0 - maintenance - movement;
+        growth.0 = 0.;
+        ledger.uptake += uptake as f64;
+        ledger.maintenance += maintenance as f64;
+        ledger.movement += movement as f64;
+    }
+}
+
+pub fn biomass_ledger_system(
+    mut ledger: ResMut<BiomassLedger>,
+    mut er_spawned: EventReader<CellSpawnedEvent>,
+    mut er_divided: EventReader<CellDividedEvent>,
+    mut er_died: EventReader<CellDiedEvent>
+) {
+    ledger.spawned += er_spawned.iter().map(|e| e.mass as f64).sum::<f64>();
+    ledger.divided += er_divided.iter().map(|e| e.mass as f64).sum::<f64>();
+    ledger.died += er_died.iter().map(|e| e.mass as f64).sum::<f64>();
+}
+
+pub fn biomass_report_system(world: &mut World) {
+    if world.resource::<Events<AppExit>>().is_empty() { return }
+    let actual = total_biomass(world);
+    info!("{}", world.resource::<BiomassLedger>().report(actual));
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::Gene;
+
+// How a single gene value changes when it mutates
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationOperator {
+    Uniform,  // Replace with a uniformly random byte
+    Gaussian { sigma: f32 },  // Add a normally distributed step, clamped to 0..=255
+    BitFlip,  // Flip one random bit
+    Creep  // Step up or down by one, saturating at 0 and 255
+}
+
+impl MutationOperator {
+
+    pub fn apply(self, v: u8, rng: &mut impl Rng) -> u8 {
+        match self {
+            MutationOperator::Uniform => rng.gen(),
+            MutationOperator::Gaussian { sigma } => {
+                (v as f32 + sigma * standard_normal(rng)).round().clamp(0., 255.) as u8
+            }
+            MutationOperator::BitFlip => v ^ (1 << rng.gen_range(0..8)),
+            MutationOperator::Creep => {
+                if rng.gen() { v.saturating_add(1) } else { v.saturating_sub(1) }
+            }
+        }
+    }
+
+}
+
+// Which operator each gene mutates with
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationConfig {
+    pub default: MutationOperator,  // Used by genes not listed in `genes`
+    pub genes: BTreeMap<Gene, MutationOperator>
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self {
+            default: MutationOperator::Uniform,
+            genes: BTreeMap::new()
+        }
+    }
+}
+
+impl MutationConfig {
+
+    pub fn operator(&self, gene: Gene) -> MutationOperator {
+        self.genes.get(&gene).copied().unwrap_or(self.default)
+    }
+
+    pub fn operators(&self) -> impl Iterator<Item = &MutationOperator> {
+        std::iter::once(&self.default).chain(self.genes.values())
+    }
+
+}
+
+// Box-Muller transform
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = 1. - rng.gen::<f32>();  // In (0, 1] so the log is finite
+    let u2: f32 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (2. * std::f32::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Genome, SimRng, SimulationConfig};
+
+    #[test]
+    fn mutation_operators_step_as_configured() {
+        let mut rng = SimRng::from_seed(17);
+        for v in [0, 1, 127, 254, u8::MAX] {
+            for _ in 0..200 {
+                let flipped = MutationOperator::BitFlip.apply(v, &mut rng);
+                assert_eq!((flipped ^ v).count_ones(), 1);
+                let crept = MutationOperator::Creep.apply(v, &mut rng);
+                assert!((crept as i32 - v as i32).abs() <= 1);
+                assert!(crept != v || v == 0 || v == u8::MAX);
+                let stepped = MutationOperator::Gaussian { sigma: 2. }.apply(v, &mut rng);
+                assert!((stepped as i32 - v as i32).abs() <= 16);
+            }
+        }
+        let wide: Vec<u8> = (0..1000).map(|_| MutationOperator::Gaussian { sigma: 1000. }.apply(127, &mut rng)).collect();
+        assert!(wide.contains(&0) && wide.contains(&u8::MAX));
+    }
+
+    #[test]
+    fn mutation_operator_is_chosen_per_gene() {
+        let json = r#"{ "evolution_probability": 1.0, "mutation": { "default": "creep", "genes": { "charge": "bit_flip", "eat_rate": { "gaussian": { "sigma": 3.0 } } } } }"#;
+        let config: SimulationConfig = serde_json::from_str(json).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.mutation.operator(Gene::Charge), MutationOperator::BitFlip);
+        assert_eq!(config.mutation.operator(Gene::EatRate), MutationOperator::Gaussian { sigma: 3. });
+        assert_eq!(config.mutation.operator(Gene::ForceRange), MutationOperator::Creep);
+        let mut rng = SimRng::from_seed(19);
+        let parent = Genome::random(&config, &mut rng);
+        let child = Genome::mutate_from(&parent, &config, &mut rng);
+        for (a, b) in parent.force_range.iter().zip(child.force_range.iter()) {
+            assert!((*a as i32 - *b as i32).abs() <= 1);
+        }
+        assert_eq!((parent.charge ^ child.charge).count_ones(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat}
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{BiomassLedger, Body, BoundaryMode, Eaten, Genome, SimulationClock, SimulationConfig, WorldBounds};
+
+pub const HEATMAP_HOTKEY: KeyCode = KeyCode::N;
+
+// Rates are per reference tick
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NutrientConfig {
+    pub enabled: bool,
+    pub cell_size: f32,  // Side of a grid square in world units
+    pub capacity: f32,  // Nutrients a full square holds
+    pub regeneration: f32,  // Fraction of a square's shortfall to capacity that grows back
+    pub diffusion: f32,  // Fraction of the difference to each neighbouring square that flows over
+    pub heatmap: bool  // Draw the field behind the cells in the windowed app
+}
+
+impl Default for NutrientConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cell_size: 20.,
+            capacity: 2.,
+            regeneration: 0.005,
+            diffusion: 0.05,
+            heatmap: true
+        }
+    }
+}
+
+// Nutrients on a grid of squares covering the world, row by row from the bottom left
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct NutrientField {
+    pub columns: usize,
+    pub rows: usize,
+    pub cell_size: f32,
+    pub origin: Vec2,  // Bottom left corner of the first square
+    pub values: Vec<f32>,
+    #[serde(skip)]
+    scratch: Vec<f32>
+}
+
+impl NutrientField {
+
+    // A full field over the bounds
+    pub fn new(bounds: &WorldBounds, config: &NutrientConfig) -> Self {
+        let columns = (bounds.width / config.cell_size).ceil().max(1.) as usize;
+        let rows = (bounds.height / config.cell_size).ceil().max(1.) as usize;
+        Self {
+            columns,
+            rows,
+            cell_size: config.cell_size,
+            origin: -Vec2::new(columns as f32, rows as f32) * config.cell_size * 0.5,
+            values: vec![config.capacity; columns * rows],
+            scratch: Vec::new()
+        }
+    }
+
+    // Column and row of the square containing pos
+    pub fn square(&self, pos: Vec2) -> Option<(usize, usize)> {
+        let p = (pos - self.origin) / self.cell_size;
+        if p.x < 0. || p.y < 0. || p.x >= self.columns as f32 || p.y >= self.rows as f32 { return None }
+        Some((p.x as usize, p.y as usize))
+    }
+
+    fn center(&self, column: usize, row: usize) -> Vec2 {
+        self.origin + (Vec2::new(column as f32, row as f32) + 0.5) * self.cell_size
+    }
+
+    pub fn total(&self) -> f64 {
+        self.values.iter().map(|v| *v as f64).sum()
+    }
+
+    pub fn regenerate(&mut self, config: &NutrientConfig, dt: f32) {
+        let rate = (config.regeneration * dt).min(1.);
+        for v in self.values.iter_mut() {
+            *v += (config.capacity - *v) * rate;
+        }
+    }
+
+    // Exchanges nutrients between edge sharing squares. The edges of the grid are closed
+    // unless `wrap` joins them like a toroidal world.
+    pub fn diffuse(&mut self, rate: f32, wrap: bool) {
+        let (columns, rows) = (self.columns, self.rows);
+        let neighbour = |i: usize, d: isize, n: usize| -> Option<usize> {
+            let j = i as isize + d;
+            if (0..n as isize).contains(&j) { Some(j as usize) }
+            else if wrap { Some(j.rem_euclid(n as isize) as usize) }
+            else { None }
+        };
+        self.scratch.clear();
+        self.scratch.extend_from_slice(&self.values);
+        for row in 0..rows {
+            for column in 0..columns {
+                let v = self.scratch[row * columns + column];
+                let mut flow = 0.;
+                for (dc, dr) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    if let (Some(c), Some(r)) = (neighbour(column, dc, columns), neighbour(row, dr, rows)) {
+                        flow += self.scratch[r * columns + c] - v;
+                    }
+                }
+                self.values[row * columns + column] = v + flow * rate;
+            }
+        }
+    }
+
+    // Takes up to `demand` from the squares under a cell, in proportion to what each holds,
+    // and returns how much it got. A cell smaller than a square feeds on the one it is in.
+    pub fn consume(&mut self, pos: Vec2, radius: f32, demand: f32) -> f32 {
+        let Some(home) = self.square(pos) else { return 0. };
+        // Squares within the cell's bounding box, clipped to the grid
+        let to_square = |x: f32, origin: f32, n: usize| (((x - origin) / self.cell_size).max(0.) as usize).min(n - 1);
+        let lower = (to_square(pos.x - radius, self.origin.x, self.columns), to_square(pos.y - radius, self.origin.y, self.rows));
+        let last = (to_square(pos.x + radius, self.origin.x, self.columns), to_square(pos.y + radius, self.origin.y, self.rows));
+        let covered = |field: &Self, column: usize, row: usize| {
+            (column, row) == home || field.center(column, row).distance_squared(pos) <= radius * radius
+        };
+        let mut available = 0.;
+        for row in lower.1..=last.1 {
+            for column in lower.0..=last.0 {
+                if covered(self, column, row) {
+                    available += self.values[row * self.columns + column];
+                }
+            }
+        }
+        if available <= 0. { return 0. }
+        let share = (demand / available).min(1.);
+        let mut taken = 0.;
+        for row in lower.1..=last.1 {
+            for column in lower.0..=last.0 {
+                if covered(self, column, row) {
+                    let v = &mut self.values[row * self.columns + column];
+                    let take = *v * share;
+                    *v -= take;
+                    taken += take;
+                }
+            }
+        }
+        taken
+    }
+
+}
+
+// Starts a full field unless a snapshot brought one along
+pub fn setup_nutrient_field(
+    mut commands: Commands,
+    field: Option<Res<NutrientField>>,
+    bounds: Res<WorldBounds>,
+    config: Res<SimulationConfig>
+) {
+    if config.nutrients.enabled && field.is_none() {
+        commands.insert_resource(NutrientField::new(&bounds, &config.nutrients));
+    }
+}
+
+pub fn nutrient_field_system(
+    field: Option<ResMut<NutrientField>>,
+    clock: Res<SimulationClock>,
+    config: Res<SimulationConfig>
+) {
+    let Some(mut field) = field else { return };
+    let dt = clock.dt();
+    field.regenerate(&config.nutrients, dt);
+    field.diffuse(config.nutrients.diffusion * dt, config.boundary == BoundaryMode::Toroidal);
+}
+
+// Cells feed on the field in query order, so where they crowd the first served get the most
+pub fn forage_system(
+    field: Option<ResMut<NutrientField>>,
+    mut query: Query<(Entity, &mut Body, &Genome)>,
+    eaten: Res<Eaten>,
+    mut ledger: ResMut<BiomassLedger>,
+    clock: Res<SimulationClock>,
+    config: Res<SimulationConfig>
+) {
+    let Some(mut field) = field else { return };
+    let dt = clock.dt();
+    for (_, mut body, genome) in query.iter_mut().filter(|(entity, _, _)| !eaten.0.contains(entity)) {
+        let demand = config.uptake.decode(genome.uptake) * dt;
+        let radius = body.radius();
+        let taken = field.consume(body.pos, radius, demand);
+        body.mass += taken;
+        ledger.foraged += taken as f64;
+    }
+}
+
+#[derive(Component)]
+pub struct NutrientHeatmap(Handle<Image>);
+
+// Draws the field as a texture stretched over the world, behind the cells
+pub fn nutrient_heatmap_system(
+    mut commands: Commands,
+    field: Option<Res<NutrientField>>,
+    keys: Res<Input<KeyCode>>,
+    mut images: ResMut<Assets<Image>>,
+    mut q_heatmap: Query<(&NutrientHeatmap, &mut Visibility)>,
+    config: Res<SimulationConfig>
+) {
+    let Some(field) = field else { return };
+    let Ok((heatmap, mut visibility)) = q_heatmap.get_single_mut() else {
+        let image = Image::new(
+            Extent3d { width: field.columns as u32, height: field.rows as u32, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            vec![0; field.columns * field.rows * 4],
+            TextureFormat::Rgba8UnormSrgb
+        );
+        let size = Vec2::new(field.columns as f32, field.rows as f32) * field.cell_size;
+        let handle = images.add(image);
+        commands.spawn(SpriteBundle {
+            texture: handle.clone(),
+            sprite: Sprite { custom_size: Some(size), ..default() },
+            transform: Transform::from_translation((field.origin + size * 0.5).extend(-1.)),
+            visibility: Visibility { is_visible: config.nutrients.heatmap },
+            ..default()
+        }).insert(NutrientHeatmap(handle));
+        return
+    };
+    if keys.just_pressed(HEATMAP_HOTKEY) {
+        visibility.is_visible = !visibility.is_visible;
+    }
+    if !visibility.is_visible { return }
+    let Some(image) = images.get_mut(&heatmap.0) else { return };
+    let capacity = config.nutrients.capacity.max(f32::EPSILON);
+    // Texture rows run top down, field rows bottom up
+    for (row, values) in field.values.chunks(field.columns).enumerate() {
+        let start = (field.rows - 1 - row) * field.columns * 4;
+        for (pixel, v) in image.data[start..start + field.columns * 4].chunks_mut(4).zip(values) {
+            let level = ((v / capacity).clamp(0., 1.) * 255.) as u8;
+            pixel.copy_from_slice(&[level / 4, level / 2, level / 8, 255]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nutrient_field_conserves_what_it_moves() {
+        let config = NutrientConfig { cell_size: 10., capacity: 4., ..default() };
+        let mut field = NutrientField::new(&WorldBounds { width: 100., height: 60. }, &config);
+        assert_eq!((field.columns, field.rows), (10, 6));
+        assert_eq!(field.square(Vec2::new(-50., -30.)), Some((0, 0)));
+        assert_eq!(field.square(Vec2::new(49.9, 29.9)), Some((9, 5)));
+        assert_eq!(field.square(Vec2::new(50., 0.)), None);
+
+        // Feeding takes from the squares under the cell, never more than asked or there is
+        let before = field.total();
+        let taken = field.consume(Vec2::new(0., 0.), 12., 5.);
+        assert!((taken - 5.).abs() < 1e-4);
+        assert!((before - field.total() - taken as f64).abs() < 1e-4);
+        let taken = field.consume(Vec2::new(-45., -25.), 1., 10.);
+        assert!((taken - 4.).abs() < 1e-4, "a small cell took {}", taken);
+        assert_eq!(field.consume(Vec2::new(-45., -25.), 1., 10.), 0.);
+        assert_eq!(field.consume(Vec2::new(200., 0.), 50., 10.), 0.);
+
+        // Diffusion spreads nutrients out without creating or destroying any
+        for wrap in [false, true] {
+            let mut field = field.clone();
+            let before = field.total();
+            let spread = |f: &NutrientField| f.values.iter().cloned().fold(f32::MIN, f32::max) - f.values.iter().cloned().fold(f32::MAX, f32::min);
+            let initial_spread = spread(&field);
+            for _ in 0..100 {
+                field.diffuse(0.2, wrap);
+            }
+            assert!((field.total() - before).abs() < 1e-3, "diffusion changed the total from {} to {}", before, field.total());
+            assert!(spread(&field) < initial_spread * 0.5);
+        }
+
+        // Regeneration refills towards capacity
+        for _ in 0..2000 {
+            field.regenerate(&config, 1.);
+        }
+        assert!(field.values.iter().all(|v| (v - config.capacity).abs() < 1e-3));
+    }
+}
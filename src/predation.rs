@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+
+use crate::{BiomassLedger, Body, Contacts, Genome, Growth, SimulationClock, SimulationConfig};
+
+// Cells eaten whole this tick, for the death system to remove
+#[derive(Resource, Default)]
+pub struct Eaten(pub Vec<Entity>);
+
+impl Genome {
+
+    // Mass per reference tick this cell would bite off the other, before the other bites back
+    pub fn eat_rate_on(&self, other: &Genome, config: &SimulationConfig) -> f32 {
+        config.eat_rate.decode(self.eat_rate[self.charge_type(other.charge)])
+    }
+
+}
+
+// Every overlapping pair once, in query order. The cell with the higher eat rate on the
+// other bites off the difference, keeping `predation_efficiency` of it. Prey left lighter
+// than the minimum size is eaten whole. Transfers go into Growth for metabolism to apply,
+// which also keeps track of what prey has already lost this tick.
+pub fn predation_system(
+    q_cells: Query<(Entity, &Body, &Genome)>,
+    mut q_growth: Query<&mut Growth>,
+    contacts: Res<Contacts>,
+    mut eaten: ResMut<Eaten>,
+    mut ledger: ResMut<BiomassLedger>,
+    clock: Res<SimulationClock>,
+    config: Res<SimulationConfig>
+) {
+    let dt = clock.dt();
+    let efficiency = config.metabolism.predation_efficiency;
+    for (e1, body1, g1) in q_cells.iter() {
+        for e2 in contacts.0.get(&e1).into_iter().flatten().filter(|e2| e1 < **e2) {
+            let Ok((_, body2, g2)) = q_cells.get(*e2) else { continue };
+            let net = g1.eat_rate_on(g2, &config) - g2.eat_rate_on(g1, &config);
+            let (predator, prey, prey_mass) = if net > 0. { (e1, *e2, body2.mass) } else { (*e2, e1, body1.mass) };
+            if net == 0. || eaten.0.contains(&prey) || eaten.0.contains(&predator) { continue }
+            let Ok([mut predator_growth, mut prey_growth]) = q_growth.get_many_mut([predator, prey]) else { continue };
+            let remaining = (prey_mass + prey_growth.0).max(0.);
+            let mut bite = (net.abs() * dt).min(remaining);
+            if remaining - bite < config.minimum_size {
+                bite = remaining;
+                eaten.0.push(prey);
+            }
+            prey_growth.0 -= bite;
+            predator_growth.0 += bite * efficiency;
+            ledger.predation_loss += (bite * (1. - efficiency)) as f64;
+        }
+    }
+}
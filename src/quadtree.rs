@@ -28,6 +28,7 @@ pub struct CollisionQuadtree {
     is_split: bool
 }
 
+#[allow(clippy::redundant_field_names)]
 impl CollisionQuadtree {
     
     pub fn spawn(x: f32, y: f32, w: f32, h: f32) -> Self {
@@ -147,12 +148,61 @@ impl CollisionQuadtree {
         }
     }
     
+    // Collects every entity that could lie within `radius` of `p`
     pub fn retrieve(&self, p: Vec2, radius: f32, returned: &mut Vec<EntityBody>) {
-        let i = self.find_index(p, radius);
-        if i != -1 && self.is_split {
-            self.nodes[i as usize].retrieve(p, radius, returned);
+        if self.is_split {
+            let i = self.find_index(p, radius);
+            if i != -1 {
+                self.nodes[i as usize].retrieve(p, radius, returned);
+            }
+            else {
+                // The query straddles a midline, so descend into each quadrant it reaches
+                let midx = self.bounds.x + self.bounds.width / 2.;
+                let midy = self.bounds.y + self.bounds.height / 2.;
+                let left = p.x - radius < midx;
+                let right = p.x + radius > midx;
+                let top = p.y + radius > midy;
+                let bottom = p.y - radius < midy;
+                for (index, overlaps) in [right && top, left && top, left && bottom, right && bottom].iter().enumerate() {
+                    if *overlaps {
+                        self.nodes[index].retrieve(p, radius, returned);
+                    }
+                }
+            }
         }
         returned.extend(self.entities.iter());
     }
  
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn straddling_queries_reach_every_quadrant() {
+        let mut tree = CollisionQuadtree::spawn(-500., -500., 1000., 1000.);
+        let mut all = Vec::new();
+        for i in 0..40 {
+            for j in 0..40 {
+                let e = EntityBody {
+                    entity: Entity::from_raw(i * 40 + j),
+                    position: Vec2::new(i as f32 * 25. - 490., j as f32 * 25. - 490.),
+                    radius: 1.
+                };
+                tree.insert(e);
+                all.push(e);
+            }
+        }
+        assert!(tree.is_split);
+        // On the root's midlines, on a child's and off them all
+        for (p, radius) in [(Vec2::ZERO, 60.), (Vec2::new(0., 300.), 40.), (Vec2::new(-250., -250.), 30.), (Vec2::new(-370., 120.), 20.)] {
+            let mut found = Vec::new();
+            tree.retrieve(p, radius, &mut found);
+            let found: Vec<Entity> = found.iter().map(|e| e.entity).collect();
+            for e in all.iter().filter(|e| e.position.distance(p) <= radius + e.radius) {
+                assert!(found.contains(&e.entity), "{:?} missed at {:?} around {:?}", e.entity, e.position, p);
+            }
+        }
+    }
+}
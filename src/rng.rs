@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+// The one source of randomness for the simulation. Systems that run sequentially draw
+// from it directly, anything that runs in parallel draws from a stream forked off it.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct SimRng {
+    seed: u64,
+    rng: ChaCha8Rng
+}
+
+impl SimRng {
+
+    pub fn from_seed(seed: u64) -> Self {
+        Self { seed, rng: ChaCha8Rng::seed_from_u64(seed) }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::from_seed(rand::thread_rng().gen())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // An independent stream whose state is determined by this one
+    pub fn fork(&mut self) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.rng.gen())
+    }
+
+}
+
+impl RngCore for SimRng {
+
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+
+}
+
+// Per cell stream so cells can be updated in parallel without losing determinism
+#[derive(Component)]
+pub struct CellRng(pub ChaCha8Rng);
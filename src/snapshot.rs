@@ -0,0 +1,172 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{Acceleration, Body, Cell, CellRng, CellSpawnEvent, CellSpawnedEvent, Genome, Growth, NextCellId, NutrientField, SimRng, Species, SimulationConfig, SimulationTick, Velocity};
+
+pub const SNAPSHOT_VERSION: u32 = 8;
+
+pub const SNAPSHOT_HOTKEY: KeyCode = KeyCode::F5;
+
+// Everything needed to resume a run
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub tick: u64,
+    pub config: SimulationConfig,
+    pub rng: SimRng,
+    pub nutrients: Option<NutrientField>,
+    pub next_id: u32,  // Ids of cells that already died stay taken
+    pub cells: Vec<CellSnapshot>,
+    pub pending: Vec<CellSpawnEvent>  // Daughters of the last division, spawned by the next tick
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CellSnapshot {
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub generation: u32,
+    pub pos: [f32; 2],
+    pub mass: f32,
+    pub vel: [f32; 2],
+    pub growth: f32,
+    pub previous_acc: Option<[f32; 2]>,  // Last tick's acceleration, which velocity Verlet carries over
+    pub genome: Genome,
+    pub rng: ChaCha8Rng
+}
+
+impl Snapshot {
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        let snapshot: Self = serde_json::from_str(&text).map_err(|e| format!("Failed to parse snapshot {}: {}", path.display(), e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!("Snapshot {} has version {}, expected {}", path.display(), snapshot.version, SNAPSHOT_VERSION))
+        }
+        snapshot.config.validate().map_err(|e| format!("Snapshot {}: {}", path.display(), e))?;
+        snapshot.check_charge_types(snapshot.config.charge_types).map_err(|e| format!("Snapshot {}: {}", path.display(), e))?;
+        Ok(snapshot)
+    }
+
+    // Every genome must have interaction tables for the given number of charge types
+    pub fn check_charge_types(&self, charge_types: usize) -> Result<(), String> {
+        if let Some(c) = self.cells.iter().find(|c| c.genome.charge_types() != charge_types) {
+            return Err(format!("cell {} has {} charge types, the config has {}", c.id, c.genome.charge_types(), charge_types))
+        }
+        match self.pending.iter().find(|e| e.genome.charge_types() != charge_types) {
+            Some(e) => Err(format!("a daughter of cell {:?} has {} charge types, the config has {}", e.parent, e.genome.charge_types(), charge_types)),
+            None => Ok(())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("Failed to write snapshot {}: {}", path.display(), e))
+    }
+
+}
+
+// The parts of the world a snapshot is taken from
+#[derive(SystemParam)]
+pub struct SnapshotSource<'w, 's> {
+    tick: Res<'w, SimulationTick>,
+    config: Res<'w, SimulationConfig>,
+    rng: Res<'w, SimRng>,
+    nutrients: Option<Res<'w, NutrientField>>,
+    next_id: Res<'w, NextCellId>,
+    pending: Res<'w, Events<CellSpawnEvent>>,
+    cells: Query<'w, 's, (&'static Cell, &'static Body, &'static Velocity, &'static Acceleration, &'static Growth, &'static Genome, &'static CellRng)>
+}
+
+impl<'w, 's> SnapshotSource<'w, 's> {
+
+    pub fn capture(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            tick: self.tick.0,
+            config: self.config.clone(),
+            rng: self.rng.clone(),
+            nutrients: self.nutrients.as_deref().cloned(),
+            next_id: self.next_id.0,
+            cells: self.cells.iter().map(|(cell, body, velocity, acceleration, growth, genome, rng)| CellSnapshot {
+                id: cell.id,
+                parent: cell.parent,
+                generation: cell.generation,
+                pos: body.pos.to_array(),
+                mass: body.mass,
+                vel: velocity.vel.to_array(),
+                growth: growth.0,
+                previous_acc: acceleration.previous.map(|acc| acc.to_array()),
+                genome: genome.clone(),
+                rng: rng.0.clone()
+            }).collect(),
+            pending: self.pending.get_reader().iter(&self.pending).cloned().collect()
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        match self.capture().save(path) {
+            Ok(()) => info!("Saved snapshot of {} cells to {}", self.cells.iter().len(), path.display()),
+            Err(e) => error!("{}", e)
+        }
+    }
+
+}
+
+// Where to write a snapshot when the app exits
+#[derive(Resource)]
+pub struct SnapshotOnExit(pub PathBuf);
+
+// Spawns the cells of the Snapshot resource in place of the random population
+pub fn restore_snapshot_system(
+    mut commands: Commands,
+    snapshot: Res<Snapshot>,
+    mut tick: ResMut<SimulationTick>,
+    mut next_id: ResMut<NextCellId>,
+    mut ew_spawned: EventWriter<CellSpawnedEvent>,
+    mut requests: ResMut<Events<CellSpawnEvent>>,
+    config: Res<SimulationConfig>
+) {
+    tick.0 = snapshot.tick;
+    next_id.0 = snapshot.next_id;
+    for c in snapshot.cells.iter() {
+        ew_spawned.send(CellSpawnedEvent { tick: snapshot.tick, id: c.id, parent: c.parent, generation: c.generation, pos: Vec2::from(c.pos), mass: c.mass });
+        commands.spawn( Cell { id: c.id, parent: c.parent, generation: c.generation } )
+        .insert( Velocity { vel: Vec2::from(c.vel) } )
+        .insert( Growth(c.growth) )
+        .insert( Species(None) )
+        .insert( Acceleration { acc: Vec2::ZERO, previous: c.previous_acc.map(Vec2::from) } )
+        .insert( c.genome.clone() )
+        .insert( Body { pos: Vec2::from(c.pos), mass: c.mass } )
+        .insert( CellRng(c.rng.clone()) );
+    }
+    for e in snapshot.pending.iter() {
+        requests.send(e.clone());
+    }
+    // The field only carries over into runs that use one
+    if let Some(field) = snapshot.nutrients.as_ref().filter(|_| config.nutrients.enabled) {
+        commands.insert_resource(field.clone());
+    }
+    commands.remove_resource::<Snapshot>();
+}
+
+pub fn snapshot_hotkey_system(
+    keys: Res<Input<KeyCode>>,
+    source: SnapshotSource
+) {
+    if keys.just_pressed(SNAPSHOT_HOTKEY) {
+        source.save(Path::new(&format!("snapshot-{}.json", source.tick.0)));
+    }
+}
+
+pub fn snapshot_on_exit_system(
+    mut er_exit: EventReader<AppExit>,
+    path: Res<SnapshotOnExit>,
+    source: SnapshotSource
+) {
+    if er_exit.iter().next().is_some() {
+        source.save(&path.0);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{fmt::Write, fs, path::{Path, PathBuf}};
+
+use bevy::{app::AppExit, ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{every_ticks, Gene, Genome, SimulationClock, SimulationConfig, SimulationTick};
+
+pub const LEGEND_FONT: &str = "fonts/DejaVuSansMono.ttf";
+
+// Most species the on-screen legend lists
+const LEGEND_ROWS: usize = 12;
+
+// Members a species' representative is picked from, spread evenly over the species
+const MEDOID_SAMPLE: usize = 32;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesConfig {
+    pub threshold: f32,  // Largest genome distance to a species' representative for a cell to join it
+    pub interval: f64  // Simulated seconds between clustering passes
+}
+
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 24.,
+            interval: 1.
+        }
+    }
+}
+
+// None until a clustering pass first places the cell, daughters start out in their parent's
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Species(pub Option<u32>);
+
+pub struct SpeciesInfo {
+    pub id: u32,
+    pub representative: Genome,
+    pub members: usize
+}
+
+// Species alive as of the last clustering pass, oldest first. A species keeps its id for as
+// long as cells stay close to its representative, which follows the species' medoid.
+#[derive(Resource, Default)]
+pub struct SpeciesRegistry {
+    next_id: u32,
+    pub species: Vec<SpeciesInfo>
+}
+
+// Member counts of every species at every clustering pass. Only kept when --species-log is given.
+#[derive(Resource, Default)]
+pub struct SpeciesHistory {
+    pub rows: Vec<(u64, u32, usize)>
+}
+
+#[derive(Resource)]
+pub struct SpeciesLogOnExit(pub PathBuf);
+
+#[derive(Component)]
+pub struct SpeciesLegend;
+
+impl Genome {
+
+    // Mean absolute difference over every gene value, from 0 for identical genomes to 255
+    pub fn distance(&self, other: &Genome) -> f32 {
+        let (sum, n) = Gene::ALL.iter()
+            .flat_map(|gene| self.gene(*gene).iter().zip(other.gene(*gene)))
+            .fold((0u32, 0u32), |(sum, n), (a, b)| (sum + a.abs_diff(*b) as u32, n + 1));
+        sum as f32 / n.max(1) as f32
+    }
+
+}
+
+impl SpeciesRegistry {
+
+    // Puts every genome in the closest species within `threshold`, founding new species for
+    // genomes that fit none, then re-centers the species and drops the extinct ones.
+    // Returns the species id of each genome.
+    pub fn cluster(&mut self, genomes: &[&Genome], threshold: f32) -> Vec<u32> {
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); self.species.len()];
+        for (i, genome) in genomes.iter().enumerate() {
+            let closest = self.species.iter()
+                .enumerate()
+                .map(|(s, species)| (s, species.representative.distance(genome)))
+                .filter(|(_, d)| *d <= threshold)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            match closest {
+                Some((s, _)) => members[s].push(i),
+                None => {
+                    self.species.push(SpeciesInfo { id: self.next_id, representative: (*genome).clone(), members: 0 });
+                    self.next_id += 1;
+                    members.push(vec![i]);
+                }
+            }
+        }
+        let mut ids = vec![0; genomes.len()];
+        for (species, members) in self.species.iter_mut().zip(members.iter()) {
+            species.members = members.len();
+            for i in members {
+                ids[*i] = species.id;
+            }
+            if !members.is_empty() {
+                species.representative = genomes[medoid(genomes, members)].clone();
+            }
+        }
+        self.species.retain(|s| s.members > 0);
+        ids
+    }
+
+}
+
+// The sampled member with the least total distance to the other sampled members
+fn medoid(genomes: &[&Genome], members: &[usize]) -> usize {
+    let step = (members.len() / MEDOID_SAMPLE).max(1);
+    let sample: Vec<usize> = members.iter().step_by(step).copied().collect();
+    *sample.iter()
+        .min_by(|a, b| {
+            let cost = |i: usize| sample.iter().map(|j| genomes[i].distance(genomes[*j])).sum::<f32>();
+            cost(**a).total_cmp(&cost(**b))
+        })
+        .unwrap()
+}
+
+// Distinct, stable color for a species id
+pub fn species_color(id: u32) -> Color {
+    Color::hsl((id as f32 * 137.508) % 360., 0.7, 0.6)
+}
+
+// Piped from PhysicsStep. Runs at the start of the tick after a pass is due, when the
+// world is as the due tick left it.
+pub fn species_step(
+    In(physics): In<ShouldRun>,
+    clock: Res<SimulationClock>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>
+) -> ShouldRun {
+    every_ticks(physics, tick.0.saturating_sub(1), clock.ticks_in(config.species.interval))
+}
+
+// Updates every cell's Species in place, so cells keep their archetype and query order
+pub fn species_system(
+    mut query: Query<(&Genome, &mut Species)>,
+    mut registry: ResMut<SpeciesRegistry>,
+    mut history: Option<ResMut<SpeciesHistory>>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>
+) {
+    let genomes: Vec<&Genome> = query.iter().map(|(genome, _)| genome).collect();
+    let ids = registry.cluster(&genomes, config.species.threshold);
+    for ((_, mut species), id) in query.iter_mut().zip(ids) {
+        if species.0 != Some(id) {
+            species.0 = Some(id);
+        }
+    }
+    if let Some(history) = history.as_mut() {
+        for species in registry.species.iter() {
+            history.rows.push((tick.0 - 1, species.id, species.members));
+        }
+    }
+}
+
+pub fn species_color_system(
+    mut query: Query<(&Species, &mut Sprite), Changed<Species>>
+) {
+    for (species, mut sprite) in query.iter_mut() {
+        if let Some(id) = species.0 {
+            sprite.color = species_color(id);
+        }
+    }
+}
+
+pub fn setup_species_legend(mut commands: Commands) {
+    commands.spawn(
+        TextBundle::default().with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { left: Val::Px(10.), top: Val::Px(10.), ..default() },
+            ..default()
+        })
+    ).insert(SpeciesLegend);
+}
+
+// Lists the largest species with their colors and member counts
+pub fn species_legend_system(
+    asset_server: Res<AssetServer>,
+    registry: Res<SpeciesRegistry>,
+    mut query: Query<&mut Text, With<SpeciesLegend>>
+) {
+    if !registry.is_changed() { return }
+    let font = asset_server.load(LEGEND_FONT);
+    let mut largest: Vec<&SpeciesInfo> = registry.species.iter().collect();
+    largest.sort_by(|a, b| b.members.cmp(&a.members).then(a.id.cmp(&b.id)));
+    for mut text in query.iter_mut() {
+        text.sections = largest.iter().take(LEGEND_ROWS).map(|s| TextSection::new(
+            format!("#{:<5} {:>5}\n", s.id, s.members),
+            TextStyle { font: font.clone(), font_size: 16., color: species_color(s.id) }
+        )).collect();
+        if registry.species.len() > LEGEND_ROWS {
+            text.sections.push(TextSection::new(
+                format!("+{} more\n", registry.species.len() - LEGEND_ROWS),
+                TextStyle { font: font.clone(), font_size: 16., color: Color::GRAY }
+            ));
+        }
+    }
+}
+
+impl SpeciesHistory {
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("tick,species,members\n");
+        for (tick, id, members) in self.rows.iter() {
+            writeln!(out, "{},{},{}", tick, id, members).unwrap();
+        }
+        out
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_csv()).map_err(|e| format!("Failed to write species log {}: {}", path.display(), e))
+    }
+
+}
+
+pub fn species_log_on_exit_system(
+    mut er_exit: EventReader<AppExit>,
+    path: Res<SpeciesLogOnExit>,
+    history: Res<SpeciesHistory>
+) {
+    if er_exit.iter().next().is_some() {
+        match history.save(&path.0) {
+            Ok(()) => info!("Saved species counts to {}", path.0.display()),
+            Err(e) => error!("{}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MutationConfig, MutationOperator, SimRng};
+
+    #[test]
+    fn species_ids_are_stable_while_genomes_stay_close() {
+        let config = SimulationConfig { charge_types: 4, evolution_probability: 0.5, ..default() };
+        let mut rng = SimRng::from_seed(37);
+        let mut a = Genome::zeroed(4);
+        let mut b = Genome::zeroed(4);
+        for gene in Gene::ALL {
+            a.gene_mut(gene).fill(40);
+            b.gene_mut(gene).fill(200);
+        }
+        let mut registry = SpeciesRegistry::default();
+        let ids = registry.cluster(&[&a, &b, &a, &b], 24.);
+        assert_eq!(ids, [0, 1, 0, 1]);
+        // Small drift keeps the ids, a distant genome founds a new species
+        let creep = SimulationConfig { mutation: MutationConfig { default: MutationOperator::Creep, ..default() }, ..config };
+        let a2 = Genome::mutate_from(&a, &creep, &mut rng);
+        let c = Genome::zeroed(4);
+        assert!(a.distance(&a2) <= 1.);
+        let ids = registry.cluster(&[&b, &a2, &c], 24.);
+        assert_eq!(ids, [1, 0, 2]);
+        // Extinct species are dropped and their ids aren't reused
+        let ids = registry.cluster(&[&c, &a], 24.);
+        assert_eq!(ids, [2, 0]);
+        assert_eq!(registry.species.iter().map(|s| (s.id, s.members)).collect::<Vec<_>>(), [(0, 1), (2, 1)]);
+        let ids = registry.cluster(&[&b], 24.);
+        assert_eq!(ids, [3]);
+        assert_eq!(a.distance(&a), 0.);
+        assert_eq!(a.distance(&c), 40.);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}};
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{every_ticks, Body, Cell, Gene, Genome, NextCellId, PhysicsStep, PhysicsSystem, SimulationClock, SimulationConfig, SimulationTick, Velocity};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    pub interval: f64  // Simulated seconds between rows
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self { interval: 1. }
+    }
+}
+
+// Appends a row of population statistics to a CSV file every `stats.interval` simulated seconds
+pub struct StatsPlugin {
+    pub path: PathBuf
+}
+
+// Births are told apart by cell ids, which count up, and deaths by the population left.
+// Founders count as births, dividing cells as deaths.
+#[derive(Resource)]
+pub struct StatsRecorder {
+    out: BufWriter<File>,
+    next_id: u32,  // As of the last row
+    population: usize
+}
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        let recorder = match StatsRecorder::create(&self.path) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                error!("{}, not recording statistics", e);
+                return
+            }
+        };
+        app.insert_resource(recorder)
+            .add_startup_system_to_stage(StartupStage::PostStartup, stats_start_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(RunCriteria::pipe(PhysicsStep, stats_step))
+                    .with_system(stats_sample_system.before(PhysicsSystem::Spawn))
+            );
+    }
+}
+
+impl StatsRecorder {
+
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create stats file {}: {}", path.display(), e))?;
+        let mut recorder = Self { out: BufWriter::new(file), next_id: 0, population: 0 };
+        let mut header = String::from("tick,time,population,births,deaths,total_mass,mean_speed");
+        for gene in Gene::ALL.iter().filter(|gene| !gene.is_table()) {
+            header += &format!(",{0}_mean,{0}_variance", gene.name());
+        }
+        recorder.write_line(&header);
+        Ok(recorder)
+    }
+
+    // Flushed line by line so the file is complete whenever the app stops
+    fn write_line(&mut self, line: &str) {
+        if let Err(e) = writeln!(self.out, "{}", line).and_then(|_| self.out.flush()) {
+            error!("Failed to write stats: {}", e);
+        }
+    }
+
+}
+
+// Counts from the cells a resumed snapshot starts with, rather than from none
+fn stats_start_system(
+    mut recorder: ResMut<StatsRecorder>,
+    next_id: Res<NextCellId>,
+    query: Query<(), With<Cell>>
+) {
+    recorder.next_id = next_id.0;
+    recorder.population = query.iter().count();
+}
+
+// Piped from PhysicsStep. Samples at the start of the tick after a row is due, when the
+// world is as the due tick left it. There is nothing to sample before the first tick.
+fn stats_step(
+    In(physics): In<ShouldRun>,
+    clock: Res<SimulationClock>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>
+) -> ShouldRun {
+    match tick.0 {
+        0 | 1 if physics != ShouldRun::No => ShouldRun::NoAndCheckAgain,
+        _ => every_ticks(physics, tick.0.saturating_sub(1), clock.ticks_in(config.stats.interval))
+    }
+}
+
+fn stats_sample_system(
+    mut recorder: ResMut<StatsRecorder>,
+    tick: Res<SimulationTick>,
+    clock: Res<SimulationClock>,
+    next_id: Res<NextCellId>,
+    query: Query<(&Body, &Velocity, &Genome)>
+) {
+    let n = query.iter().len();
+    let births = next_id.0 - recorder.next_id;
+    let deaths = recorder.population + births as usize - n;
+    let (total_mass, total_speed) = query.iter().fold((0., 0.), |(m, s), (body, velocity, _)| (m + body.mass, s + velocity.vel.length()));
+    let mean = |total: f32| if n > 0 { total / n as f32 } else { 0. };
+    let due = tick.0 - 1;
+    let mut line = format!("{},{:.3},{},{},{},{},{}", due, clock.seconds_at(due), n, births, deaths, total_mass, mean(total_speed));
+    for gene in Gene::ALL.iter().filter(|gene| !gene.is_table()) {
+        let values = || query.iter().map(|(_, _, genome)| genome.gene(*gene)[0] as f32);
+        let m = mean(values().sum());
+        let variance = mean(values().map(|v| (v - m) * (v - m)).sum());
+        line += &format!(",{},{}", m, variance);
+    }
+    recorder.write_line(&line);
+    recorder.next_id = next_id.0;
+    recorder.population = n;
+}
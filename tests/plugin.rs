@@ -0,0 +1,43 @@
+use bevy::prelude::*;
+use bevy_genetic_particles::*;
+
+fn run_until_tick(app: &mut App, tick: u64) {
+    while app.world.resource::<SimulationTick>().0 < tick {
+        app.update();
+    }
+}
+
+// Embeds the simulation in an app of our own and feeds it a cell through the public API
+#[test]
+fn plugin_runs_in_an_embedding_app() {
+    let config = SimulationConfig::default();
+    let mut rng = SimRng::from_seed(5);
+    let genome = Genome::random(&config, &mut rng);
+    let frame_dt = SimulationClock::new(config.ticks_per_second).step;
+    let mut app = App::new();
+    app.add_plugin(HeadlessPlugin { frame_dt, max_ticks: None, max_wall_time: None })
+        .add_plugin(GeneticParticlesPlugin {
+            config,
+            snapshot: None,
+            options: SimulationOptions { seed: Some(5), ..default() }
+        });
+    run_until_tick(&mut app, 1);
+    let before = app.world.query::<&Cell>().iter(&app.world).count();
+    assert!(before > 0);
+    app.world.resource_mut::<Events<CellSpawnEvent>>().send(CellSpawnEvent {
+        pos: Vec2::new(100., 100.),
+        vel: Vec2::ZERO,
+        size: 2000.,
+        genome,
+        parent: None,
+        generation: 0,
+        species: None
+    });
+    run_until_tick(&mut app, 2);
+    let cells: Vec<(&Cell, &Body)> = app.world.query::<(&Cell, &Body)>().iter(&app.world).collect();
+    assert_eq!(cells.len(), before + 1);
+    // Ids count up, so ours is the newest
+    let (cell, body) = cells.iter().max_by_key(|(cell, _)| cell.id).unwrap();
+    assert_eq!((cell.parent, cell.generation), (None, 0));
+    assert!((body.mass - 2000.).abs() < 1., "spawned at {}", body.mass);
+}